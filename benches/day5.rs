@@ -1,25 +1,91 @@
-use aoc2019::intcode;
+use aoc2019::intcode::compile::Program;
+use aoc2019::intcode::{self, asm, Machine, State};
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::fs;
 
+fn run_to_halt(program: &intcode::Memory, input: i64, cached: bool) {
+    let mut machine = Machine::new(program.clone());
+    machine.set_decode_cache(cached);
+    machine.push_input(input);
+
+    while machine.run().unwrap() != State::Halted {}
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let input = &fs::read_to_string("inputs/5.txt").unwrap();
 
     c.bench_function("day5/part1/load", |b| {
         b.iter(|| {
-            intcode::load_program(input);
+            intcode::load_program(input).unwrap();
         });
     });
 
     c.bench_function("day5/part1/execute", |b| {
-        let mut sink = std::io::sink();
-        let program = intcode::load_program(input);
+        let program = intcode::load_program(input).unwrap();
         b.iter(|| {
             let mut mem = program.clone();
             let inputs = [5i64];
-            intcode::execute(&mut mem, &mut inputs.iter(), &mut sink);
+            let mut outputs = vec![];
+            intcode::execute(&mut mem, &mut inputs.iter(), &mut outputs)
+                .unwrap();
         });
     });
+
+    let program = intcode::load_program(input).unwrap();
+
+    c.bench_function("day5/part2/uncached", |b| {
+        b.iter(|| run_to_halt(&program, 5, false));
+    });
+
+    c.bench_function("day5/part2/cached", |b| {
+        b.iter(|| run_to_halt(&program, 5, true));
+    });
+
+    // A tight loop, where most instructions run many times over.
+    let countdown = asm::assemble(
+        "loop: add [counter], #-1, [counter]
+               jt [counter], #loop
+               out [counter]
+               halt
+         counter: db 10000",
+    )
+    .unwrap();
+
+    c.bench_function("loop/uncached", |b| {
+        b.iter(|| run_to_halt(&countdown, 0, false));
+    });
+
+    c.bench_function("loop/cached", |b| {
+        b.iter(|| run_to_halt(&countdown, 0, true));
+    });
+
+    c.bench_function("loop/compiled", |b| {
+        let program = Program::compile(&countdown);
+        b.iter(|| {
+            let mut mem = countdown.clone();
+            let mut outputs = vec![];
+            program.run(&mut mem, &mut [].iter(), &mut outputs).unwrap();
+        });
+    });
+
+    let day2 =
+        intcode::load_program(&fs::read_to_string("inputs/2.txt").unwrap())
+            .unwrap();
+
+    for &cached in &[false, true] {
+        let name = if cached { "cached" } else { "uncached" };
+
+        c.bench_function(&format!("day2/sweep/{}", name), |b| {
+            b.iter(|| {
+                for noun in 0..10 {
+                    let mut program = day2.clone();
+                    program[1] = noun;
+                    program[2] = noun;
+                    run_to_halt(&program, 0, cached);
+                }
+            });
+        });
+    }
 }
 
 criterion_group!(benches, criterion_benchmark);
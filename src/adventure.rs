@@ -0,0 +1,697 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use snafu::{ResultExt, Snafu};
+
+use crate::intcode::{self, ascii::Ascii, Memory};
+
+/// Items that are known to end the game or leave the droid stuck.
+pub const DEADLY: [&str; 5] = [
+    "escape pod",
+    "giant electromagnet",
+    "infinite loop",
+    "molten lava",
+    "photons",
+];
+
+/// Steps a single command may take before the game is assumed to be stuck
+/// in a loop.
+const COMMAND_BUDGET: usize = 1_000_000;
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Game program failed: {}", source))]
+    Intcode { source: intcode::Error },
+    #[snafu(display("Expected a room description, got:\n{}", text))]
+    NoRoom { text: String },
+    #[snafu(display("Game ended unexpectedly:\n{}", text))]
+    GameOver { text: String },
+    #[snafu(display("No room {:?} on the map", name))]
+    UnknownRoom { name: String },
+    #[snafu(display("Never found the pressure-sensitive floor"))]
+    NoCheckpoint,
+    #[snafu(display("No combination of items gets past the checkpoint"))]
+    NoCombination,
+}
+
+/// What the game printed in response to a command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub text: String,
+    /// The game won't accept any more commands.
+    pub over: bool,
+}
+
+/// A text adventure that can be saved by cloning it.
+pub trait Game: Clone {
+    /// Runs up to the first prompt.
+    fn start(&mut self) -> Result<Response, Error>;
+
+    fn send(&mut self, command: &str) -> Result<Response, Error>;
+}
+
+/// The adventure as an intcode program talking ASCII.
+#[derive(Debug, Clone)]
+pub struct IntcodeGame {
+    ascii: Ascii,
+}
+
+impl IntcodeGame {
+    pub fn new(program: Memory) -> IntcodeGame {
+        let mut ascii = Ascii::new(program);
+        ascii.machine_mut().set_cycle_detection(Some(1000));
+
+        IntcodeGame { ascii }
+    }
+
+    fn run(&mut self) -> Result<Response, Error> {
+        let machine = self.ascii.machine_mut();
+        let limit = machine.steps() + COMMAND_BUDGET;
+        machine.set_step_limit(Some(limit));
+
+        let reply = self.ascii.run().context(Intcode)?;
+        Ok(Response {
+            over: reply.is_halted(),
+            text: reply.text,
+        })
+    }
+}
+
+impl Game for IntcodeGame {
+    fn start(&mut self) -> Result<Response, Error> {
+        self.run()
+    }
+
+    fn send(&mut self, command: &str) -> Result<Response, Error> {
+        self.ascii.send_line(command);
+        self.run()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Room {
+    pub name: String,
+    pub description: String,
+    pub doors: Vec<String>,
+    pub items: Vec<String>,
+}
+
+/// Every room description in `text`, in order. Being thrown out of a room
+/// prints both the room and the one the droid lands back in.
+pub fn parse_rooms(text: &str) -> Vec<Room> {
+    let mut rooms: Vec<Room> = vec![];
+    let mut list = None;
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with("== ") && line.ends_with(" ==") {
+            rooms.push(Room {
+                name: line[3..line.len() - 3].to_string(),
+                ..Room::default()
+            });
+            list = None;
+            continue;
+        }
+
+        let room = match rooms.last_mut() {
+            Some(room) => room,
+            None => continue,
+        };
+
+        match line {
+            "Doors here lead:" => list = Some(true),
+            "Items here:" => list = Some(false),
+            "" => list = None,
+            _ if line.starts_with("- ") => {
+                let entry = line[2..].to_string();
+                match list {
+                    Some(true) => room.doors.push(entry),
+                    Some(false) => room.items.push(entry),
+                    None => (),
+                }
+            }
+            _ if room.description.is_empty() && list.is_none() => {
+                room.description = line.to_string()
+            }
+            _ => (),
+        }
+    }
+
+    rooms
+}
+
+fn opposite(door: &str) -> &str {
+    match door {
+        "north" => "south",
+        "south" => "north",
+        "east" => "west",
+        "west" => "east",
+        other => other,
+    }
+}
+
+/// How an attempt at the pressure-sensitive floor went.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Weight {
+    TooLight,
+    TooHeavy,
+    Right,
+}
+
+fn weigh(response: &Response) -> Weight {
+    if response.text.contains("are heavier than") {
+        Weight::TooLight
+    } else if response.text.contains("are lighter than") {
+        Weight::TooHeavy
+    } else {
+        Weight::Right
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    /// What the droid was carrying when it got past the floor.
+    pub items: Vec<String>,
+    /// Everything printed on the way through.
+    pub text: String,
+}
+
+impl Solution {
+    /// The first run of digits in the final message, which is the airlock
+    /// password.
+    pub fn password(&self) -> Option<&str> {
+        let start = self.text.find(|c: char| c.is_ascii_digit())?;
+        let rest = &self.text[start..];
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+
+        Some(&rest[..end])
+    }
+}
+
+/// Plays a text adventure: maps it, picks up everything safe and then
+/// works out what to carry past the pressure-sensitive floor.
+#[derive(Debug, Clone)]
+pub struct Player<G> {
+    game: G,
+    here: String,
+    rooms: HashMap<String, Room>,
+    /// Where each door out of each room leads. Ordered so that routes, and
+    /// so the commands sent, are the same from run to run.
+    doors: BTreeMap<(String, String), String>,
+    inventory: Vec<String>,
+    deadly: HashSet<String>,
+    /// The room next to the floor and the door leading onto it.
+    checkpoint: Option<(String, String)>,
+}
+
+impl<G: Game> Player<G> {
+    pub fn new(mut game: G) -> Result<Player<G>, Error> {
+        let response = game.start()?;
+        let room = parse_rooms(&response.text).pop().ok_or(Error::NoRoom {
+            text: response.text,
+        })?;
+
+        let mut player = Player {
+            game,
+            here: room.name.clone(),
+            rooms: HashMap::new(),
+            doors: BTreeMap::new(),
+            inventory: vec![],
+            deadly: DEADLY.iter().map(|s| s.to_string()).collect(),
+            checkpoint: None,
+        };
+        player.rooms.insert(room.name.clone(), room);
+
+        Ok(player)
+    }
+
+    pub fn rooms(&self) -> &HashMap<String, Room> {
+        &self.rooms
+    }
+
+    pub fn inventory(&self) -> &[String] {
+        &self.inventory
+    }
+
+    pub fn here(&self) -> &str {
+        &self.here
+    }
+
+    /// Items the player won't pick up.
+    pub fn deadly(&self) -> &HashSet<String> {
+        &self.deadly
+    }
+
+    pub fn avoid(&mut self, item: &str) {
+        self.deadly.insert(item.to_string());
+    }
+
+    fn send(&mut self, command: &str) -> Result<Response, Error> {
+        let response = self.game.send(command)?;
+        if response.over {
+            return GameOver {
+                text: response.text,
+            }
+            .fail();
+        }
+
+        Ok(response)
+    }
+
+    /// Goes through `door` and returns the room that was entered, which
+    /// may not be where the player ends up.
+    fn go(&mut self, door: &str) -> Result<Room, Error> {
+        let response = self.send(door)?;
+        let mut rooms = parse_rooms(&response.text);
+
+        let last = rooms.pop().ok_or(Error::NoRoom {
+            text: response.text,
+        })?;
+        let entered = rooms.into_iter().next().unwrap_or_else(|| last.clone());
+
+        if entered.name != last.name {
+            self.checkpoint = Some((last.name.clone(), door.to_string()));
+        }
+
+        self.doors.insert(
+            (self.here.clone(), door.to_string()),
+            entered.name.clone(),
+        );
+        self.rooms
+            .entry(entered.name.clone())
+            .or_insert(entered.clone());
+        self.rooms.entry(last.name.clone()).or_insert(last.clone());
+        self.here = last.name;
+
+        Ok(entered)
+    }
+
+    /// Tries picking `item` up in a copy of the game, and checks the game
+    /// carries on and the droid can still move afterwards.
+    fn is_safe(&self, item: &str) -> bool {
+        let mut game = self.game.clone();
+        let door =
+            match self.rooms.get(&self.here).and_then(|r| r.doors.first()) {
+                Some(door) => door,
+                None => return false,
+            };
+
+        let took = game.send(&format!("take {}", item));
+        let moved = took.and_then(|r| match r.over {
+            true => Ok(r),
+            false => game.send(door),
+        });
+
+        match moved {
+            Ok(r) => !r.over && !parse_rooms(&r.text).is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    fn collect(&mut self) -> Result<(), Error> {
+        let items = self.rooms[&self.here].items.clone();
+
+        for item in items {
+            if self.deadly.contains(&item) {
+                continue;
+            }
+            if !self.is_safe(&item) {
+                self.deadly.insert(item);
+                continue;
+            }
+
+            self.send(&format!("take {}", item))?;
+            self.inventory.push(item.clone());
+            self.rooms
+                .get_mut(&self.here)
+                .unwrap()
+                .items
+                .retain(|i| *i != item);
+        }
+
+        Ok(())
+    }
+
+    /// Visits every room depth first, picking up each safe item, and ends
+    /// up back where it started.
+    pub fn explore(&mut self) -> Result<(), Error> {
+        self.visit(&mut HashSet::new())
+    }
+
+    fn visit(&mut self, visited: &mut HashSet<String>) -> Result<(), Error> {
+        visited.insert(self.here.clone());
+        self.collect()?;
+
+        let here = self.here.clone();
+        let doors = self.rooms[&here].doors.clone();
+
+        for door in doors {
+            if self.doors.contains_key(&(here.clone(), door.clone())) {
+                continue;
+            }
+
+            let entered = self.go(&door)?;
+            if self.here != entered.name {
+                // Bounced straight back, so there's nothing to undo.
+                continue;
+            }
+
+            let back = opposite(&door).to_string();
+            self.doors
+                .insert((entered.name.clone(), back.clone()), here.clone());
+
+            if !visited.contains(&entered.name) {
+                self.visit(visited)?;
+            }
+            self.go(&back)?;
+        }
+
+        Ok(())
+    }
+
+    /// Walks to `target` through doors that have already been used.
+    pub fn travel(&mut self, target: &str) -> Result<(), Error> {
+        let mut previous: HashMap<String, (String, String)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(self.here.clone());
+
+        while let Some(room) = queue.pop_front() {
+            if room == target {
+                break;
+            }
+            for ((from, door), to) in &self.doors {
+                if *from == room
+                    && !previous.contains_key(to)
+                    && *to != self.here
+                {
+                    previous.insert(to.clone(), (from.clone(), door.clone()));
+                    queue.push_back(to.clone());
+                }
+            }
+        }
+
+        let mut path = vec![];
+        let mut at = target.to_string();
+        while at != self.here {
+            let (from, door) =
+                previous.get(&at).cloned().ok_or(Error::UnknownRoom {
+                    name: target.to_string(),
+                })?;
+            path.push(door);
+            at = from;
+        }
+
+        for door in path.iter().rev() {
+            self.go(door)?;
+        }
+
+        Ok(())
+    }
+
+    /// Goes to the checkpoint and tries subsets of the inventory on the
+    /// floor until one is the right weight. Each attempt starts from a copy
+    /// of the game holding everything, so nothing has to be picked back up.
+    pub fn solve(&mut self) -> Result<Solution, Error> {
+        let (room, door) =
+            self.checkpoint.clone().ok_or(Error::NoCheckpoint)?;
+        self.travel(&room)?;
+
+        let n = self.inventory.len();
+        let mut too_light: Vec<u32> = vec![];
+        let mut too_heavy: Vec<u32> = vec![];
+
+        for subset in 0..1u32 << n {
+            // Fewer items than a light set, or more than a heavy one, can't
+            // work.
+            if too_light.iter().any(|&light| subset & !light == 0)
+                || too_heavy.iter().any(|&heavy| heavy & !subset == 0)
+            {
+                continue;
+            }
+
+            let mut game = self.game.clone();
+            for (i, item) in self.inventory.iter().enumerate() {
+                if subset & 1 << i == 0 {
+                    game.send(&format!("drop {}", item))?;
+                }
+            }
+
+            let response = game.send(&door)?;
+            match weigh(&response) {
+                Weight::TooLight => too_light.push(subset),
+                Weight::TooHeavy => too_heavy.push(subset),
+                Weight::Right => {
+                    self.game = game;
+                    return Ok(Solution {
+                        items: (0..n)
+                            .filter(|i| subset & 1 << i != 0)
+                            .map(|i| self.inventory[i].clone())
+                            .collect(),
+                        text: response.text,
+                    });
+                }
+            }
+        }
+
+        NoCombination.fail()
+    }
+}
+
+/// Plays the intcode adventure from start to finish.
+pub fn autoplay(program: Memory) -> Result<Solution, Error> {
+    let mut player = Player::new(IntcodeGame::new(program))?;
+    player.explore()?;
+    player.solve()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HULL_BREACH: &str = "
+
+== Hull Breach ==
+You got in through a hole in the floor here.
+
+Doors here lead:
+- north
+- east
+
+Items here:
+- candy cane
+
+Command?
+";
+
+    #[test]
+    fn parsing_rooms() {
+        let rooms = parse_rooms(HULL_BREACH);
+
+        assert_eq!(
+            vec![Room {
+                name: "Hull Breach".to_string(),
+                description: "You got in through a hole in the floor here."
+                    .to_string(),
+                doors: vec!["north".to_string(), "east".to_string()],
+                items: vec!["candy cane".to_string()],
+            }],
+            rooms
+        );
+    }
+
+    #[test]
+    fn password() {
+        let solution = Solution {
+            items: vec![],
+            text: "You should be able to get in by typing 2424308736 on the \
+                   keypad"
+                .to_string(),
+        };
+
+        assert_eq!(Some("2424308736"), solution.password());
+    }
+
+    /// A little adventure:
+    ///
+    /// ```text
+    ///   Kitchen (lava, mug) -- Hall (coin) -- Lab (magnet, book)
+    ///                           |
+    ///                        Checkpoint (rock) -- Floor
+    /// ```
+    ///
+    /// The floor wants the coin, mug and book, which weigh 1, 2 and 4;
+    /// the rock weighs 8.
+    #[derive(Debug, Clone)]
+    struct Mock {
+        here: &'static str,
+        items: HashMap<&'static str, Vec<&'static str>>,
+        carrying: Vec<&'static str>,
+        stuck: bool,
+    }
+
+    impl Mock {
+        fn new() -> Mock {
+            let items = vec![
+                ("Kitchen", vec!["molten lava", "mug"]),
+                ("Hall", vec!["coin"]),
+                ("Lab", vec!["magnet", "book"]),
+                ("Checkpoint", vec!["rock"]),
+            ];
+
+            Mock {
+                here: "Hall",
+                items: items.into_iter().collect(),
+                carrying: vec![],
+                stuck: false,
+            }
+        }
+
+        fn doors(room: &str) -> Vec<(&'static str, &'static str)> {
+            match room {
+                "Kitchen" => vec![("east", "Hall")],
+                "Hall" => vec![
+                    ("west", "Kitchen"),
+                    ("east", "Lab"),
+                    ("south", "Checkpoint"),
+                ],
+                "Lab" => vec![("west", "Hall")],
+                "Checkpoint" => vec![("north", "Hall"), ("east", "Floor")],
+                _ => vec![],
+            }
+        }
+
+        fn describe(&self, room: &str) -> String {
+            let mut text =
+                format!("\n== {} ==\nA room.\n\nDoors here lead:\n", room);
+            for (door, _) in Mock::doors(room) {
+                text += &format!("- {}\n", door);
+            }
+            let items = &self.items[room];
+            if !items.is_empty() {
+                text += "\nItems here:\n";
+                for item in items {
+                    text += &format!("- {}\n", item);
+                }
+            }
+
+            text + "\nCommand?\n"
+        }
+
+        fn weight(&self) -> i64 {
+            self.carrying
+                .iter()
+                .map(|item| match *item {
+                    "coin" => 1,
+                    "mug" => 2,
+                    "book" => 4,
+                    "rock" => 8,
+                    _ => 0,
+                })
+                .sum()
+        }
+    }
+
+    impl Game for Mock {
+        fn start(&mut self) -> Result<Response, Error> {
+            Ok(Response {
+                text: self.describe(self.here),
+                over: false,
+            })
+        }
+
+        fn send(&mut self, command: &str) -> Result<Response, Error> {
+            let reply = |text: String, over| Ok(Response { text, over });
+
+            if let Some(item) = command.strip_prefix("take ") {
+                let items = self.items.get_mut(self.here).unwrap();
+                let at = items.iter().position(|i| *i == item).unwrap();
+                let item = items.remove(at);
+                self.carrying.push(item);
+
+                return match item {
+                    "molten lava" => reply("You melt.".to_string(), true),
+                    "magnet" => {
+                        self.stuck = true;
+                        reply(
+                            "It sticks to you.\nCommand?\n".to_string(),
+                            false,
+                        )
+                    }
+                    _ => reply(format!("You take the {}.\n", item), false),
+                };
+            }
+
+            if let Some(item) = command.strip_prefix("drop ") {
+                self.carrying.retain(|i| *i != item);
+                return reply(format!("You drop the {}.\n", item), false);
+            }
+
+            if self.stuck {
+                return reply("You can't move!\nCommand?\n".to_string(), false);
+            }
+
+            let to = Mock::doors(self.here)
+                .into_iter()
+                .find(|(door, _)| *door == command)
+                .map(|(_, to)| to)
+                .expect("no such door");
+
+            if to != "Floor" {
+                self.here = to;
+                return reply(self.describe(to), false);
+            }
+
+            let alert = match self.weight() {
+                7 => {
+                    return reply(
+                        "You may proceed. Type 1234.".to_string(),
+                        true,
+                    )
+                }
+                w if w < 7 => "Droids on this ship are heavier than",
+                _ => "Droids on this ship are lighter than",
+            };
+            reply(
+                format!(
+                    "\n== Floor ==\n{} the detected value!\n\n{}",
+                    alert,
+                    self.describe(self.here)
+                ),
+                false,
+            )
+        }
+    }
+
+    #[test]
+    fn exploring() {
+        let mut player = Player::new(Mock::new()).unwrap();
+        player.explore().unwrap();
+
+        assert_eq!("Hall", player.here());
+        assert_eq!(5, player.rooms().len());
+        assert!(player.deadly().contains("magnet"));
+
+        let mut inventory = player.inventory().to_vec();
+        inventory.sort();
+        assert_eq!(vec!["book", "coin", "mug", "rock"], inventory);
+    }
+
+    #[test]
+    fn solving() {
+        let mut player = Player::new(Mock::new()).unwrap();
+        player.explore().unwrap();
+        let solution = player.solve().unwrap();
+
+        let mut items = solution.items.clone();
+        items.sort();
+        assert_eq!(vec!["book", "coin", "mug"], items);
+        assert_eq!(Some("1234"), solution.password());
+    }
+
+    #[test]
+    fn solving_needs_a_checkpoint() {
+        let mut player = Player::new(Mock::new()).unwrap();
+
+        assert_eq!(Err(Error::NoCheckpoint), player.solve());
+    }
+}
@@ -0,0 +1,162 @@
+use itertools::Itertools;
+use snafu::{ResultExt, Snafu};
+
+use crate::intcode::{self, Machine, Memory, State};
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Amplifier {} failed: {}", amplifier, source))]
+    Intcode {
+        amplifier: usize,
+        source: intcode::Error,
+    },
+    #[snafu(display("Amplifier {} stopped without an output", amplifier))]
+    NoOutput { amplifier: usize },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Mode {
+    /// Each amplifier runs once and passes its output to the next.
+    Series,
+    /// The last amplifier feeds back into the first until they all halt.
+    Feedback,
+}
+
+fn boot(program: &Memory, phases: &[i64]) -> Vec<Machine> {
+    phases
+        .iter()
+        .map(|phase| {
+            let mut machine = Machine::new(program.clone());
+            machine.push_input(*phase);
+            machine
+        })
+        .collect()
+}
+
+pub fn run_series(program: &Memory, phases: &[i64]) -> Result<i64, Error> {
+    let mut signal = 0;
+
+    for (amplifier, machine) in boot(program, phases).iter_mut().enumerate() {
+        machine.push_input(signal);
+
+        signal = match machine.run().context(Intcode { amplifier })? {
+            State::Output(value) => value,
+            _ => return NoOutput { amplifier }.fail(),
+        };
+    }
+
+    Ok(signal)
+}
+
+pub fn run_feedback(program: &Memory, phases: &[i64]) -> Result<i64, Error> {
+    let mut machines = boot(program, phases);
+    let last = machines.len().saturating_sub(1);
+    let mut signal = 0;
+    let mut thrusters = None;
+
+    'outer: loop {
+        for (amplifier, machine) in machines.iter_mut().enumerate() {
+            machine.push_input(signal);
+
+            match machine.run().context(Intcode { amplifier })? {
+                State::Output(value) => signal = value,
+                State::Halted => break 'outer,
+                State::NeedsInput => return NoOutput { amplifier }.fail(),
+            }
+
+            if amplifier == last {
+                thrusters = Some(signal);
+            }
+        }
+    }
+
+    thrusters.ok_or(Error::NoOutput { amplifier: last })
+}
+
+/// Tries every ordering of `settings` and returns the one that sends the
+/// highest signal to the thrusters, along with that signal.
+pub fn best_phases(
+    program: &Memory,
+    settings: &[i64],
+    mode: Mode,
+) -> Result<(Vec<i64>, i64), Error> {
+    let run = match mode {
+        Mode::Series => run_series,
+        Mode::Feedback => run_feedback,
+    };
+
+    let mut best: Option<(Vec<i64>, i64)> = None;
+
+    for phases in settings.iter().copied().permutations(settings.len()) {
+        let signal = run(program, &phases)?;
+
+        if best.as_ref().map_or(true, |(_, s)| signal > *s) {
+            best = Some((phases, signal));
+        }
+    }
+
+    Ok(best.unwrap_or((vec![], 0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(text: &str) -> Memory {
+        intcode::load_program(text).unwrap()
+    }
+
+    #[test]
+    fn series() {
+        let program = program("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
+
+        assert_eq!(Ok(43210), run_series(&program, &[4, 3, 2, 1, 0]));
+    }
+
+    #[test]
+    fn best_series() {
+        let program = program(
+            "3,23,3,24,1002,24,10,24,1002,23,-1,23,\
+             101,5,23,23,1,24,23,23,4,23,99,0,0",
+        );
+
+        assert_eq!(
+            Ok((vec![0, 1, 2, 3, 4], 54321)),
+            best_phases(&program, &[0, 1, 2, 3, 4], Mode::Series)
+        );
+    }
+
+    #[test]
+    fn feedback() {
+        let program = program(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,\
+             27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        );
+
+        assert_eq!(Ok(139629729), run_feedback(&program, &[9, 8, 7, 6, 5]));
+    }
+
+    #[test]
+    fn best_feedback() {
+        let program = program(
+            "3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,\
+             54,-5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,\
+             53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10",
+        );
+
+        assert_eq!(
+            Ok((vec![9, 7, 8, 5, 6], 18216)),
+            best_phases(&program, &[5, 6, 7, 8, 9], Mode::Feedback)
+        );
+    }
+
+    #[test]
+    fn silent_amplifier() {
+        let program = program("3,0,3,0,99");
+
+        assert_eq!(
+            Err(Error::NoOutput { amplifier: 0 }),
+            run_series(&program, &[1, 2])
+        );
+    }
+}
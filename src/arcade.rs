@@ -0,0 +1,294 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use snafu::{ResultExt, Snafu};
+
+use crate::grid::{self, Point};
+use crate::intcode::{self, Machine, Memory, State};
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Game program failed: {}", source))]
+    Intcode { source: intcode::Error },
+    #[snafu(display("Unknown tile {} at {}, {}", tile, x, y))]
+    UnknownTile { x: i64, y: i64, tile: i64 },
+    #[snafu(display("Tile drawn off the screen at {}, {}", x, y))]
+    OffScreen { x: i64, y: i64 },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Tile {
+    Empty,
+    Wall,
+    Block,
+    Paddle,
+    Ball,
+}
+
+impl Tile {
+    fn from_id(id: i64) -> Option<Tile> {
+        use Tile::*;
+
+        match id {
+            0 => Some(Empty),
+            1 => Some(Wall),
+            2 => Some(Block),
+            3 => Some(Paddle),
+            4 => Some(Ball),
+            _ => None,
+        }
+    }
+
+    fn glyph(self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Wall => '#',
+            Tile::Block => '=',
+            Tile::Paddle => '-',
+            Tile::Ball => 'o',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Joystick {
+    Left,
+    Neutral,
+    Right,
+}
+
+impl Joystick {
+    fn value(self) -> i64 {
+        match self {
+            Joystick::Left => -1,
+            Joystick::Neutral => 0,
+            Joystick::Right => 1,
+        }
+    }
+}
+
+/// Everything the game has drawn so far.
+#[derive(Debug, Clone, Default)]
+pub struct Screen {
+    tiles: HashMap<Point, Tile>,
+    score: i64,
+    ball: Option<Point>,
+    paddle: Option<Point>,
+}
+
+impl Screen {
+    pub fn tiles(&self) -> &HashMap<Point, Tile> {
+        &self.tiles
+    }
+
+    pub fn score(&self) -> i64 {
+        self.score
+    }
+
+    pub fn ball(&self) -> Option<Point> {
+        self.ball
+    }
+
+    pub fn paddle(&self) -> Option<Point> {
+        self.paddle
+    }
+
+    pub fn count(&self, tile: Tile) -> usize {
+        self.tiles.values().filter(|&&t| t == tile).count()
+    }
+
+    fn draw(&mut self, x: i64, y: i64, value: i64) -> Result<(), Error> {
+        if (x, y) == (-1, 0) {
+            self.score = value;
+            return Ok(());
+        }
+
+        let tile = Tile::from_id(value).ok_or(Error::UnknownTile {
+            x,
+            y,
+            tile: value,
+        })?;
+        let point = match (i32::try_from(x), i32::try_from(y)) {
+            (Ok(x), Ok(y)) => Point { x, y },
+            _ => return OffScreen { x, y }.fail(),
+        };
+
+        match tile {
+            Tile::Ball => self.ball = Some(point),
+            Tile::Paddle => self.paddle = Some(point),
+            _ => (),
+        }
+        self.tiles.insert(point, tile);
+
+        Ok(())
+    }
+
+    /// The score line followed by the playing field.
+    pub fn render(&self) -> String {
+        let field =
+            grid::render(&self.tiles, |tile| tile.map_or(' ', |t| t.glyph()));
+
+        format!("Score: {}\n{}", self.score, field)
+    }
+}
+
+/// The game program hooked up to a screen and a joystick.
+#[derive(Debug, Clone)]
+pub struct Cabinet {
+    machine: Machine,
+    screen: Screen,
+    pending: Vec<i64>,
+}
+
+impl Cabinet {
+    pub fn new(program: Memory) -> Cabinet {
+        Cabinet {
+            machine: Machine::new(program),
+            screen: Screen::default(),
+            pending: vec![],
+        }
+    }
+
+    /// Sets the game to free play, so it keeps going rather than stopping
+    /// at the attract screen.
+    pub fn free_play(mut program: Memory) -> Cabinet {
+        program[0] = 2;
+        Cabinet::new(program)
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.machine.is_halted()
+    }
+
+    pub fn joystick(&mut self, position: Joystick) {
+        self.machine.push_input(position.value());
+    }
+
+    /// Runs until the game wants to read the joystick or ends, updating
+    /// the screen as it goes. Returns true if it's waiting on the joystick.
+    pub fn run(&mut self) -> Result<bool, Error> {
+        loop {
+            match self.machine.run().context(Intcode)? {
+                State::Output(value) => {
+                    self.pending.push(value);
+                    if let [x, y, value] = self.pending[..] {
+                        self.pending.clear();
+                        self.screen.draw(x, y, value)?;
+                    }
+                }
+                State::NeedsInput => return Ok(true),
+                State::Halted => return Ok(false),
+            }
+        }
+    }
+
+    /// Moves the paddle towards the ball.
+    pub fn autopilot_move(&self) -> Joystick {
+        let (ball, paddle) = match (self.screen.ball, self.screen.paddle) {
+            (Some(ball), Some(paddle)) => (ball, paddle),
+            _ => return Joystick::Neutral,
+        };
+
+        match ball.x.cmp(&paddle.x) {
+            Ordering::Less => Joystick::Left,
+            Ordering::Equal => Joystick::Neutral,
+            Ordering::Greater => Joystick::Right,
+        }
+    }
+
+    /// Plays until the game ends and returns the final score. `frame` is
+    /// called with the screen each time the game waits for the joystick.
+    pub fn autopilot(
+        &mut self,
+        mut frame: impl FnMut(&Screen),
+    ) -> Result<i64, Error> {
+        while self.run()? {
+            frame(&self.screen);
+            self.joystick(self.autopilot_move());
+        }
+
+        Ok(self.screen.score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::load_program;
+
+    #[test]
+    fn drawing() {
+        let program = load_program(
+            "104,1,104,2,104,3,104,6,104,5,104,4,104,-1,104,0,104,12,99",
+        )
+        .unwrap();
+        let mut cabinet = Cabinet::new(program);
+
+        assert_eq!(Ok(false), cabinet.run());
+        assert_eq!(Some(Point { x: 1, y: 2 }), cabinet.screen().paddle());
+        assert_eq!(Some(Point { x: 6, y: 5 }), cabinet.screen().ball());
+        assert_eq!(1, cabinet.screen().count(Tile::Ball));
+        assert_eq!(12, cabinet.screen().score());
+        assert_eq!(
+            "Score: 12\n-     \n      \n      \n     o\n",
+            cabinet.screen().render()
+        );
+    }
+
+    #[test]
+    fn unknown_tile() {
+        let program = load_program("104,1,104,2,104,7,99").unwrap();
+
+        assert_eq!(
+            Err(Error::UnknownTile {
+                x: 1,
+                y: 2,
+                tile: 7
+            }),
+            Cabinet::new(program).run()
+        );
+    }
+
+    #[test]
+    fn off_screen() {
+        let program = load_program("104,1,104,4294967296,104,2,99").unwrap();
+
+        assert_eq!(
+            Err(Error::OffScreen { x: 1, y: 1 << 32 }),
+            Cabinet::new(program).run()
+        );
+    }
+
+    #[test]
+    fn free_play() {
+        let cabinet = Cabinet::free_play(load_program("1,0,0,0,99").unwrap());
+
+        assert_eq!(2, cabinet.machine().memory()[0]);
+    }
+
+    #[test]
+    fn autopilot_follows_the_ball() {
+        // Draws a paddle at 1,2 and a ball at 3,1, then scores 100 plus
+        // the joystick position.
+        let program = load_program(
+            "104,1,104,2,104,3,104,3,104,1,104,4,\
+             3,100,1001,100,100,100,104,-1,104,0,4,100,99",
+        )
+        .unwrap();
+        let mut cabinet = Cabinet::new(program);
+
+        let mut frames = 0;
+        assert_eq!(Ok(101), cabinet.autopilot(|_| frames += 1));
+        assert_eq!(1, frames);
+        assert!(cabinet.is_over());
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::Result;
+use aoc2019::intcode::{self, ascii::Ascii};
+use clap::{App, Arg};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+fn main() -> Result<()> {
+    let matches = App::new("ascii")
+        .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("script")
+                .long("script")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Send the lines of FILE before reading from stdin"),
+        )
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let mut ascii = Ascii::new(intcode::load_program(input)?);
+
+    if let Some(path) = matches.value_of("script") {
+        for line in fs::read_to_string(path)?.lines() {
+            ascii.send_line(line);
+        }
+    }
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        let reply = ascii.run()?;
+
+        print!("{}", reply.text);
+        for number in &reply.numbers {
+            println!("Result: {}", number);
+        }
+
+        if reply.is_halted() {
+            return Ok(());
+        }
+
+        io::stdout().flush()?;
+        match lines.next() {
+            Some(line) => ascii.send_line(&line?),
+            None => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use anyhow::Result;
+use aoc2019::intcode::{self, asm};
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("asm")
+        .arg(Arg::with_name("INPUT").required(true))
+        .get_matches();
+
+    let source = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let mem = asm::assemble(source)?;
+
+    println!("{}", intcode::format_program(&mem));
+
+    Ok(())
+}
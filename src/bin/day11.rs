@@ -0,0 +1,28 @@
+use anyhow::Result;
+use aoc2019::grid::Point;
+use aoc2019::intcode;
+use aoc2019::painter::{Cell, PaintAndTurn, Robot};
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("day11")
+        .arg(Arg::with_name("INPUT").required(true))
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+
+    let mut robot = Robot::new(program.clone(), PaintAndTurn);
+    robot.run()?;
+    println!("Painted {} panels", robot.painted().len());
+
+    let mut robot = Robot::new(program, PaintAndTurn);
+    robot
+        .surface_mut()
+        .insert(Point { x: 0, y: 0 }, Cell::White);
+    robot.run()?;
+    print!("{}", robot.render());
+
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use aoc2019::arcade::{Cabinet, Tile};
+use aoc2019::intcode;
+use clap::{App, Arg};
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+fn main() -> Result<()> {
+    let matches = App::new("day13")
+        .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("watch")
+                .long("watch")
+                .help("Draw the game in the terminal while it plays"),
+        )
+        .arg(
+            Arg::with_name("delay")
+                .long("delay")
+                .takes_value(true)
+                .value_name("MS")
+                .default_value("20")
+                .help("Pause between frames when watching"),
+        )
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+
+    let mut cabinet = Cabinet::new(program.clone());
+    cabinet.run()?;
+    println!("Blocks: {}", cabinet.screen().count(Tile::Block));
+
+    let watch = matches.is_present("watch");
+    let delay =
+        Duration::from_millis(matches.value_of("delay").unwrap().parse()?);
+
+    let mut cabinet = Cabinet::free_play(program);
+    let score = cabinet.autopilot(|screen| {
+        if watch {
+            print!("\x1b[H\x1b[2J{}", screen.render());
+            thread::sleep(delay);
+        }
+    })?;
+    println!("Final score: {}", score);
+
+    Ok(())
+}
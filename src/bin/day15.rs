@@ -0,0 +1,39 @@
+use anyhow::{anyhow, Result};
+use aoc2019::droid::{explore, IntcodeDroid, Strategy};
+use aoc2019::intcode;
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("day15")
+        .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("backtrack")
+                .long("backtrack")
+                .help("Explore with one droid instead of cloning it"),
+        )
+        .arg(Arg::with_name("map").long("map").help("Print the maze"))
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+
+    let strategy = if matches.is_present("backtrack") {
+        Strategy::Backtrack
+    } else {
+        Strategy::Clone
+    };
+    let maze = explore(IntcodeDroid::new(program), strategy)?;
+
+    if matches.is_present("map") {
+        print!("{}", maze.render());
+    }
+
+    let path = maze
+        .shortest_path()
+        .ok_or_else(|| anyhow!("No oxygen system found"))?;
+    println!("Shortest path: {}", path);
+    println!("Fill time: {}", maze.fill_time().unwrap_or(0));
+
+    Ok(())
+}
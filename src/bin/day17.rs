@@ -0,0 +1,39 @@
+use anyhow::Result;
+use aoc2019::intcode;
+use aoc2019::scaffold::{self, compress, format_moves, MAX_LINE};
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("day17")
+        .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("limit")
+                .long("limit")
+                .takes_value(true)
+                .help("Longest line allowed for a routine"),
+        )
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+    let limit = match matches.value_of("limit") {
+        Some(limit) => limit.parse()?,
+        None => MAX_LINE,
+    };
+
+    let camera = scaffold::view(program.clone())?;
+    println!("Alignment: {}", camera.alignment());
+
+    let path = camera.path()?;
+    println!("Path: {}", format_moves(&path));
+
+    let routines = compress(&path, limit)?;
+    for (name, line) in ["Main", "A", "B", "C"].iter().zip(routines.lines()) {
+        println!("{}: {}", name, line);
+    }
+
+    println!("Dust: {}", scaffold::wake(program, &routines)?);
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use anyhow::{anyhow, Result};
+use aoc2019::intcode;
+use aoc2019::tractor::{IntcodeBeam, Scanner};
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("day19")
+        .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("area")
+                .long("area")
+                .takes_value(true)
+                .default_value("50")
+                .help("Size of the area to count affected points in"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .long("size")
+                .takes_value(true)
+                .default_value("100")
+                .help("Size of the square to fit in the beam"),
+        )
+        .arg(
+            Arg::with_name("max-y")
+                .long("max-y")
+                .takes_value(true)
+                .default_value("10000")
+                .help("How far down to look for the square"),
+        )
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+    let area: i32 = matches.value_of("area").unwrap().parse()?;
+    let size: i32 = matches.value_of("size").unwrap().parse()?;
+    let max_y: i32 = matches.value_of("max-y").unwrap().parse()?;
+
+    let mut scanner = Scanner::new(IntcodeBeam::new(program));
+
+    println!("Affected points: {}", scanner.affected(area)?);
+    println!("Intcode runs: {}", scanner.runs());
+
+    let corner = scanner
+        .square(size, max_y)?
+        .ok_or_else(|| anyhow!("No {0}x{0} square in the beam", size))?;
+    println!("Square: {}", corner.x * 10000 + corner.y);
+    println!("Intcode runs: {}", scanner.runs());
+
+    Ok(())
+}
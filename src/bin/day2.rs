@@ -1,36 +1,74 @@
-use aoc2019::intcode;
+use anyhow::Result;
+use aoc2019::intcode::{self, sweep, symbolic};
 use clap::{App, Arg};
 use std::fs;
 
-fn main() {
+const TARGET: i64 = 19690720;
+
+fn main() -> Result<()> {
     let matches = App::new("day2")
         .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("symbolic")
+                .long("symbolic")
+                .help("Solve for noun and verb instead of trying every pair"),
+        )
         .get_matches();
 
-    let input =
-        &fs::read_to_string(matches.value_of("INPUT").unwrap()).unwrap();
-
-    let initial_state = intcode::load_program(input);
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
 
-    let mut stdout = std::io::stdout();
+    let initial_state = intcode::load_program(input)?;
 
-    for noun in 0..=99 {
-        for verb in 0..=99 {
-            let mut mem = initial_state.to_vec();
-            let inputs: [i64; 0] = [];
+    if matches.is_present("symbolic") {
+        let unknowns = [1, 2].iter().map(|&address| symbolic::Unknown {
+            address,
+            range: 0..=99,
+        });
 
-            mem[1] = noun;
-            mem[2] = verb;
+        match symbolic::solve(
+            &initial_state,
+            &unknowns.collect::<Vec<_>>(),
+            0,
+            TARGET,
+        ) {
+            Some(solution) => {
+                match solution.method {
+                    symbolic::Method::Symbolic(linear) => {
+                        println!("[0] = {}", linear)
+                    }
+                    symbolic::Method::BruteForce(e) => {
+                        println!("Brute force ({})", e)
+                    }
+                }
 
-            intcode::execute(&mut mem, &mut inputs.iter(), &mut stdout);
-
-            if mem[0] == 19690720 {
+                let (noun, verb) = (solution.values[0], solution.values[1]);
                 println!("Noun: {} Verb: {}", noun, verb);
                 println!("Answer: {}", (100 * noun) + verb);
-                return;
             }
+            None => println!("Not found"),
         }
+
+        return Ok(());
+    }
+
+    let found = sweep::find_first(
+        &initial_state,
+        intcode::Limits {
+            steps: Some(1_000_000),
+            cycle_interval: Some(1000),
+        },
+        sweep::patches(&[1, 2], 0..=99),
+        |outcome| outcome.memory[0] == TARGET,
+    );
+
+    if let Some((candidate, _)) = found {
+        let (noun, verb) = (candidate.patches[0].1, candidate.patches[1].1);
+        println!("Noun: {} Verb: {}", noun, verb);
+        println!("Answer: {}", (100 * noun) + verb);
+        return Ok(());
     }
 
     println!("Not found");
+
+    Ok(())
 }
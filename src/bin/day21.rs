@@ -0,0 +1,55 @@
+use anyhow::{anyhow, Result};
+use aoc2019::intcode;
+use aoc2019::springdroid::{self, compile, synth, Expr, Mode, Outcome};
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("day21")
+        .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("EXPR")
+                .help("When to jump, e.g. \"J = !A | (!C & D & H)\"")
+                .required_unless("synthesise"),
+        )
+        .arg(
+            Arg::with_name("run")
+                .long("run")
+                .help("Use sensors A-I and RUN instead of A-D and WALK"),
+        )
+        .arg(
+            Arg::with_name("synthesise")
+                .long("synthesise")
+                .help("Work out a script from the hulls the droid falls on"),
+        )
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+
+    let mode = if matches.is_present("run") {
+        Mode::Run
+    } else {
+        Mode::Walk
+    };
+
+    if matches.is_present("synthesise") {
+        let (script, damage) = synth::synthesise(mode, 50, |script| {
+            springdroid::run(&program, script)
+        })?;
+        print!("{}", script);
+        println!("Hull damage: {}", damage);
+        return Ok(());
+    }
+
+    let expr = Expr::parse(matches.value_of("EXPR").unwrap())?;
+    let script = compile(&expr, mode)?;
+    print!("{}", script);
+
+    match springdroid::run(&program, &script)? {
+        Outcome::Survived(damage) => println!("Hull damage: {}", damage),
+        Outcome::Fell(hull) => return Err(anyhow!("Fell on {}", hull)),
+    }
+
+    Ok(())
+}
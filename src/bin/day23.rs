@@ -0,0 +1,24 @@
+use anyhow::Result;
+use aoc2019::intcode;
+use aoc2019::network::{first_nat_packet, first_repeated_wake};
+use clap::{App, Arg};
+use std::fs;
+
+const SIZE: usize = 50;
+
+fn main() -> Result<()> {
+    let matches = App::new("day23")
+        .arg(Arg::with_name("INPUT").required(true))
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+
+    let packet = first_nat_packet(&program, SIZE)?;
+    println!("First packet to the NAT: y = {}", packet.y);
+
+    let y = first_repeated_wake(&program, SIZE)?;
+    println!("First repeated wake-up: y = {}", y);
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use anyhow::{anyhow, Result};
+use aoc2019::adventure::{IntcodeGame, Player};
+use aoc2019::intcode;
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("day25")
+        .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("avoid")
+                .long("avoid")
+                .takes_value(true)
+                .multiple(true)
+                .help("Another item not to pick up"),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .long("verbose")
+                .short("v")
+                .help("Print the map and the final message"),
+        )
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+
+    let mut player = Player::new(IntcodeGame::new(program))?;
+    for item in matches.values_of("avoid").into_iter().flatten() {
+        player.avoid(item);
+    }
+    player.explore()?;
+
+    if matches.is_present("verbose") {
+        let mut rooms: Vec<_> = player.rooms().values().collect();
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        for room in rooms {
+            println!("{}: {}", room.name, room.doors.join(", "));
+        }
+        println!("Carrying: {}", player.inventory().join(", "));
+    }
+
+    let solution = player.solve()?;
+    if matches.is_present("verbose") {
+        println!("{}", solution.text.trim());
+    }
+
+    println!("Items: {}", solution.items.join(", "));
+    let password = solution
+        .password()
+        .ok_or_else(|| anyhow!("No password in the final message"))?;
+    println!("Password: {}", password);
+
+    Ok(())
+}
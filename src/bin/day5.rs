@@ -1,22 +1,58 @@
-use aoc2019::intcode;
+use anyhow::{bail, Result};
+use aoc2019::intcode::{self, trace::JsonLines};
 use clap::{App, Arg};
-use std::fs;
+use std::fs::{self, File};
+use std::io::BufWriter;
 
-fn main() {
+fn main() -> Result<()> {
     let matches = App::new("day5")
         .arg(Arg::with_name("INPUT").required(true))
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Write a JSON-lines trace of part 2 to FILE"),
+        )
         .get_matches();
 
-    let input =
-        &fs::read_to_string(matches.value_of("INPUT").unwrap()).unwrap();
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
 
     println!("Part 1");
-    let mut mem = intcode::load_program(input);
+    let mut mem = intcode::load_program(input)?;
     let inputs = [1i64];
-    intcode::execute(&mut mem, &mut inputs.iter(), &mut std::io::stdout());
+    let mut outputs = vec![];
+    intcode::execute(&mut mem, &mut inputs.iter(), &mut outputs)?;
+    let (code, tests) = match outputs.split_last() {
+        Some(split) => split,
+        None => bail!("Part 1 produced no output"),
+    };
+    println!("Test results: {:?}", tests);
+    println!("Diagnostic code: {}", code);
 
     println!("Part 2");
-    let mut mem = intcode::load_program(input);
+    let mut mem = intcode::load_program(input)?;
     let inputs = [5i64];
-    intcode::execute(&mut mem, &mut inputs.iter(), &mut std::io::stdout());
+    let mut outputs = vec![];
+    match matches.value_of("trace") {
+        Some(path) => {
+            let file = BufWriter::new(File::create(path)?);
+            let mut tracer = JsonLines::new(file, &mem)?;
+            intcode::execute_traced(
+                &mut mem,
+                &mut inputs.iter(),
+                &mut outputs,
+                &mut tracer,
+            )?;
+            tracer.finish()?;
+        }
+        None => intcode::execute(&mut mem, &mut inputs.iter(), &mut outputs)?,
+    }
+    let code = match outputs.split_last() {
+        Some((code, _)) => code,
+        None => bail!("Part 2 produced no output"),
+    };
+    println!("Diagnostic code: {}", code);
+
+    Ok(())
 }
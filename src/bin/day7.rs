@@ -0,0 +1,24 @@
+use anyhow::Result;
+use aoc2019::amplifiers::{best_phases, Mode};
+use aoc2019::intcode;
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("day7")
+        .arg(Arg::with_name("INPUT").required(true))
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let program = intcode::load_program(input)?;
+
+    let (phases, signal) =
+        best_phases(&program, &[0, 1, 2, 3, 4], Mode::Series)?;
+    println!("Series: {} with phases {:?}", signal, phases);
+
+    let (phases, signal) =
+        best_phases(&program, &[5, 6, 7, 8, 9], Mode::Feedback)?;
+    println!("Feedback: {} with phases {:?}", signal, phases);
+
+    Ok(())
+}
@@ -0,0 +1,154 @@
+use anyhow::{anyhow, bail, Result};
+use aoc2019::intcode::debugger::{Debugger, Stop};
+use aoc2019::intcode::{self, disasm, State};
+use clap::{App, Arg};
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+Commands:
+  s, step [N]          execute N instructions (default 1)
+  c, continue          run until a breakpoint, watchpoint, input or halt
+  b, break ADDR        set a breakpoint
+  d, delete ADDR       remove a breakpoint
+  w, watch ADDR        stop when the cell at ADDR changes
+  u, unwatch ADDR      remove a watchpoint
+  p, print ADDR [LEN]  print LEN cells starting at ADDR (default 1)
+  l, list [ADDR] [N]   disassemble N instructions from ADDR (default ip, 10)
+  x, next              show the next instruction
+  poke ADDR VALUE      overwrite a cell
+  i, input VALUE       queue an input
+  r, regs              show ip and relative base
+  q, quit              exit";
+
+fn arg<T: std::str::FromStr>(args: &[&str], n: usize) -> Result<Option<T>> {
+    args.get(n)
+        .map(|a| a.parse().map_err(|_| anyhow!("Can't parse {:?}", a)))
+        .transpose()
+}
+
+fn required<T: std::str::FromStr>(args: &[&str], n: usize) -> Result<T> {
+    arg(args, n)?.ok_or_else(|| anyhow!("Missing argument"))
+}
+
+fn report(debugger: &Debugger, stop: Stop) {
+    match stop {
+        Stop::Stepped => (),
+        Stop::State(State::Output(value)) => println!("Output: {}", value),
+        Stop::State(State::NeedsInput) => {
+            println!("Waiting for input (use `input VALUE`)")
+        }
+        Stop::State(State::Halted) => println!("Halted"),
+        Stop::Breakpoint(address) => println!("Breakpoint at {}", address),
+        Stop::Watchpoint { address, old, new } => {
+            println!("Watchpoint: [{}] {} -> {}", address, old, new)
+        }
+    }
+
+    if !debugger.machine().is_halted() {
+        println!("{}", debugger.next_instruction());
+    }
+}
+
+/// Returns false once the session should end.
+fn command(debugger: &mut Debugger, line: &str) -> Result<bool> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (*name, args),
+        None => return Ok(true),
+    };
+
+    match name {
+        "s" | "step" => {
+            for _ in 0..arg(args, 0)?.unwrap_or(1) {
+                let stop = debugger.step()?;
+                if stop != Stop::Stepped || debugger.machine().is_halted() {
+                    report(debugger, stop);
+                    return Ok(true);
+                }
+            }
+            report(debugger, Stop::Stepped);
+        }
+        "c" | "continue" => loop {
+            let stop = debugger.resume()?;
+            report(debugger, stop);
+            if let Stop::State(State::Output(_)) = stop {
+                continue;
+            }
+            break;
+        },
+        "b" | "break" => debugger.add_breakpoint(required(args, 0)?),
+        "d" | "delete" => {
+            if !debugger.remove_breakpoint(required(args, 0)?) {
+                println!("No such breakpoint");
+            }
+        }
+        "w" | "watch" => debugger.watch(required(args, 0)?),
+        "u" | "unwatch" => {
+            if !debugger.unwatch(required(args, 0)?) {
+                println!("No such watchpoint");
+            }
+        }
+        "p" | "print" => {
+            let start = required(args, 0)?;
+            let len: usize = arg(args, 1)?.unwrap_or(1);
+            let values = debugger.peek(start..start + len);
+
+            for (row, chunk) in values.chunks(5).enumerate() {
+                let cells: Vec<String> =
+                    chunk.iter().map(|v| v.to_string()).collect();
+                println!("{:>5}|\t{}", start + row * 5, cells.join("\t"));
+            }
+        }
+        "l" | "list" => {
+            let mut address = arg(args, 0)?.unwrap_or(debugger.machine().ip());
+            for _ in 0..arg(args, 1)?.unwrap_or(10) {
+                let instruction =
+                    disasm::decode_at(debugger.machine().memory(), address);
+                println!("{}", instruction);
+                address += instruction.size;
+            }
+        }
+        "x" | "next" => println!("{}", debugger.next_instruction()),
+        "poke" => debugger.poke(required(args, 0)?, required(args, 1)?),
+        "i" | "input" => debugger.push_input(required(args, 0)?),
+        "r" | "regs" => println!(
+            "ip: {} rb: {}",
+            debugger.machine().ip(),
+            debugger.machine().relative_base()
+        ),
+        "h" | "help" => println!("{}", HELP),
+        "q" | "quit" => return Ok(false),
+        _ => bail!("Unknown command {:?}, try `help`", name),
+    }
+
+    Ok(true)
+}
+
+fn main() -> Result<()> {
+    let matches = App::new("debugger")
+        .arg(Arg::with_name("INPUT").required(true))
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let mut debugger = Debugger::new(intcode::load_program(input)?);
+
+    println!("{}", debugger.next_instruction());
+
+    let stdin = io::stdin();
+    loop {
+        print!("(icdb) ");
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+
+        match command(&mut debugger, &line) {
+            Ok(true) => (),
+            Ok(false) => return Ok(()),
+            Err(e) => println!("Error: {}", e),
+        }
+    }
+}
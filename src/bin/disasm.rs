@@ -0,0 +1,19 @@
+use anyhow::Result;
+use aoc2019::intcode::{self, disasm};
+use clap::{App, Arg};
+use std::fs;
+
+fn main() -> Result<()> {
+    let matches = App::new("disasm")
+        .arg(Arg::with_name("INPUT").required(true))
+        .get_matches();
+
+    let input = &fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+    let mem = intcode::load_program(input)?;
+
+    for instruction in disasm::disassemble(&mem) {
+        println!("{}", instruction);
+    }
+
+    Ok(())
+}
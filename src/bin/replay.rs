@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use aoc2019::intcode::trace::Replay;
+use clap::{App, Arg};
+use std::fs::File;
+use std::io::BufReader;
+
+fn main() -> Result<()> {
+    let matches = App::new("replay")
+        .about("Rebuilds memory from a trace without re-running the program")
+        .arg(Arg::with_name("TRACE").required(true))
+        .arg(Arg::with_name("STEP").required(true))
+        .arg(
+            Arg::with_name("from")
+                .long("from")
+                .takes_value(true)
+                .help("First address to print"),
+        )
+        .arg(
+            Arg::with_name("len")
+                .long("len")
+                .takes_value(true)
+                .help("Number of cells to print"),
+        )
+        .get_matches();
+
+    let file = File::open(matches.value_of("TRACE").unwrap())?;
+    let replay = Replay::read(BufReader::new(file))?;
+
+    let step: usize = matches.value_of("STEP").unwrap().parse()?;
+    let mem = replay.memory_at(step);
+
+    match replay.event(step) {
+        Some(event) => println!("Step {}: {:>5}: {}", step, event.ip, event.op),
+        None => println!("Step {}: after the last of {}", step, replay.len()),
+    }
+
+    let from: usize = matches.value_of("from").unwrap_or("0").parse()?;
+    let len: usize = match matches.value_of("len") {
+        Some(len) => len.parse()?,
+        None => mem
+            .len()
+            .checked_sub(from)
+            .ok_or_else(|| anyhow!("--from is past the end of memory"))?,
+    };
+
+    let cells: Vec<i64> = (from..from + len).map(|a| mem[a]).collect();
+    for (row, chunk) in cells.chunks(5).enumerate() {
+        let values: Vec<String> = chunk.iter().map(|v| v.to_string()).collect();
+        println!("{:>5}|\t{}", from + row * 5, values.join("\t"));
+    }
+
+    Ok(())
+}
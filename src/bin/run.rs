@@ -0,0 +1,90 @@
+use anyhow::Result;
+use aoc2019::intcode::snapshot::Snapshot;
+use aoc2019::intcode::{self, Machine, State};
+use clap::{App, Arg};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+
+fn main() -> Result<()> {
+    let matches = App::new("run")
+        .about("Runs a program, optionally pausing to a checkpoint file")
+        .arg(Arg::with_name("INPUT").required_unless("resume"))
+        .arg(
+            Arg::with_name("input")
+                .long("input")
+                .short("i")
+                .takes_value(true)
+                .multiple(true)
+                .help("A value to queue as input"),
+        )
+        .arg(
+            Arg::with_name("steps")
+                .long("steps")
+                .takes_value(true)
+                .help("Pause after this many more instructions"),
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Save the machine to FILE when it stops"),
+        )
+        .arg(
+            Arg::with_name("resume")
+                .long("resume")
+                .takes_value(true)
+                .value_name("FILE")
+                .help("Carry on from a checkpoint instead of starting INPUT"),
+        )
+        .get_matches();
+
+    let mut machine = match matches.value_of("resume") {
+        Some(path) => {
+            let file = BufReader::new(File::open(path)?);
+            Machine::restore(Snapshot::read(file)?)
+        }
+        None => {
+            let input = fs::read_to_string(matches.value_of("INPUT").unwrap())?;
+            Machine::new(intcode::load_program(&input)?)
+        }
+    };
+
+    for value in matches.values_of("input").into_iter().flatten() {
+        machine.push_input(value.parse()?);
+    }
+
+    let pause = match matches.value_of("steps") {
+        Some(steps) => Some(machine.steps() + steps.parse::<usize>()?),
+        None => None,
+    };
+
+    loop {
+        if pause == Some(machine.steps()) {
+            println!("Paused after {} steps", machine.steps());
+            break;
+        }
+
+        match machine.step()? {
+            Some(State::Output(value)) => println!("Output: {}", value),
+            Some(State::NeedsInput) => {
+                println!("Waiting for input");
+                break;
+            }
+            Some(State::Halted) => {
+                println!("Halted after {} steps", machine.steps());
+                break;
+            }
+            None => (),
+        }
+    }
+
+    if let Some(path) = matches.value_of("checkpoint") {
+        let mut file = BufWriter::new(File::create(path)?);
+        machine.snapshot().write(&mut file)?;
+        file.flush()?;
+        println!("Saved to {}", path);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,333 @@
+use std::collections::{HashMap, VecDeque};
+
+use snafu::{ResultExt, Snafu};
+
+use crate::grid::{self, Heading, Point};
+use crate::intcode::{self, Machine, Memory, State};
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Droid program failed: {}", source))]
+    Intcode { source: intcode::Error },
+    #[snafu(display("Droid gave an unknown status {}", status))]
+    BadStatus { status: i64 },
+    #[snafu(display("Droid stopped without answering a move"))]
+    NoStatus,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Cell {
+    Wall,
+    Open,
+    Oxygen,
+}
+
+/// Something that can try to move one cell and report what it found.
+pub trait Droid {
+    fn step(&mut self, heading: Heading) -> Result<Cell, Error>;
+}
+
+/// A droid driven by an intcode program, which takes movement commands
+/// 1-4 (north, south, west, east) and answers 0 for a wall, 1 for a move and
+/// 2 for a move onto the oxygen system.
+#[derive(Debug, Clone)]
+pub struct IntcodeDroid {
+    machine: Machine,
+}
+
+impl IntcodeDroid {
+    pub fn new(program: Memory) -> IntcodeDroid {
+        IntcodeDroid {
+            machine: Machine::new(program),
+        }
+    }
+}
+
+impl Droid for IntcodeDroid {
+    fn step(&mut self, heading: Heading) -> Result<Cell, Error> {
+        let command = match heading {
+            Heading::North => 1,
+            Heading::South => 2,
+            Heading::West => 3,
+            Heading::East => 4,
+        };
+        self.machine.push_input(command);
+
+        match self.machine.run().context(Intcode)? {
+            State::Output(0) => Ok(Cell::Wall),
+            State::Output(1) => Ok(Cell::Open),
+            State::Output(2) => Ok(Cell::Oxygen),
+            State::Output(status) => BadStatus { status }.fail(),
+            _ => NoStatus.fail(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Strategy {
+    /// One droid walks every corridor and walks back out of dead ends.
+    Backtrack,
+    /// The droid is cloned at each junction, so nothing has to walk back.
+    Clone,
+}
+
+/// What the droid has seen, with the start at the origin.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Maze {
+    cells: HashMap<Point, Cell>,
+}
+
+const START: Point = Point { x: 0, y: 0 };
+
+impl Maze {
+    pub fn cells(&self) -> &HashMap<Point, Cell> {
+        &self.cells
+    }
+
+    pub fn oxygen(&self) -> Option<Point> {
+        self.cells
+            .iter()
+            .find(|(_, &cell)| cell == Cell::Oxygen)
+            .map(|(&point, _)| point)
+    }
+
+    /// Steps from `from` to every open cell reachable from it.
+    pub fn distances(&self, from: Point) -> HashMap<Point, usize> {
+        let mut distances = HashMap::new();
+        let mut queue = VecDeque::new();
+        distances.insert(from, 0);
+        queue.push_back(from);
+
+        while let Some(point) = queue.pop_front() {
+            let distance = distances[&point];
+
+            for next in grid::neighbours(point) {
+                let open = matches!(
+                    self.cells.get(&next),
+                    Some(Cell::Open) | Some(Cell::Oxygen)
+                );
+                if open && !distances.contains_key(&next) {
+                    distances.insert(next, distance + 1);
+                    queue.push_back(next);
+                }
+            }
+        }
+
+        distances
+    }
+
+    /// Fewest moves from the start to the oxygen system.
+    pub fn shortest_path(&self) -> Option<usize> {
+        self.distances(START).get(&self.oxygen()?).copied()
+    }
+
+    /// Minutes for oxygen to spread from the oxygen system to every
+    /// reachable cell.
+    pub fn fill_time(&self) -> Option<usize> {
+        self.distances(self.oxygen()?).values().max().copied()
+    }
+
+    pub fn render(&self) -> String {
+        grid::render(&self.cells, |cell| match cell {
+            Some(Cell::Wall) => '#',
+            Some(Cell::Open) => '.',
+            Some(Cell::Oxygen) => 'O',
+            None => ' ',
+        })
+    }
+
+    fn backtrack(
+        &mut self,
+        droid: &mut impl Droid,
+        at: Point,
+    ) -> Result<(), Error> {
+        for &heading in &Heading::ALL {
+            let next = heading.step(at);
+            if self.cells.contains_key(&next) {
+                continue;
+            }
+
+            let cell = droid.step(heading)?;
+            self.cells.insert(next, cell);
+
+            if cell != Cell::Wall {
+                self.backtrack(droid, next)?;
+                droid.step(heading.reverse())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn branch<D: Droid + Clone>(&mut self, droid: D) -> Result<(), Error> {
+        let mut queue = VecDeque::new();
+        queue.push_back((START, droid));
+
+        while let Some((at, droid)) = queue.pop_front() {
+            for &heading in &Heading::ALL {
+                let next = heading.step(at);
+                if self.cells.contains_key(&next) {
+                    continue;
+                }
+
+                let mut branch = droid.clone();
+                let cell = branch.step(heading)?;
+                self.cells.insert(next, cell);
+
+                if cell != Cell::Wall {
+                    queue.push_back((next, branch));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Maps every cell reachable from where `droid` starts.
+pub fn explore<D: Droid + Clone>(
+    mut droid: D,
+    strategy: Strategy,
+) -> Result<Maze, Error> {
+    let mut maze = Maze::default();
+    maze.cells.insert(START, Cell::Open);
+
+    match strategy {
+        Strategy::Backtrack => maze.backtrack(&mut droid, START)?,
+        Strategy::Clone => maze.branch(droid)?,
+    }
+
+    Ok(maze)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    /// A droid walking a map drawn in text, starting at `D`.
+    #[derive(Clone)]
+    struct MapDroid {
+        map: Vec<Vec<u8>>,
+        at: Point,
+    }
+
+    impl MapDroid {
+        fn new(map: &str) -> MapDroid {
+            let map: Vec<Vec<u8>> =
+                map.lines().map(|l| l.bytes().collect()).collect();
+            let at = map
+                .iter()
+                .enumerate()
+                .find_map(|(y, row)| {
+                    row.iter().position(|&c| c == b'D').map(|x| Point {
+                        x: x as i32,
+                        y: y as i32,
+                    })
+                })
+                .unwrap();
+
+            MapDroid { map, at }
+        }
+    }
+
+    impl Droid for MapDroid {
+        fn step(&mut self, heading: Heading) -> Result<Cell, Error> {
+            let next = heading.step(self.at);
+            let c = self
+                .map
+                .get(next.y as usize)
+                .and_then(|row| row.get(next.x as usize))
+                .copied()
+                .unwrap_or(b' ');
+
+            let cell = match c {
+                b'.' | b'D' => Cell::Open,
+                b'O' => Cell::Oxygen,
+                _ => return Ok(Cell::Wall),
+            };
+            self.at = next;
+            Ok(cell)
+        }
+    }
+
+    const EXAMPLE: &str = " ##
+#D.##
+#.#..#
+#.O.#
+ ###  ";
+
+    #[test]
+    fn backtracking() {
+        let mut droid = MapDroid::new(EXAMPLE);
+        let mut maze = Maze::default();
+        maze.cells.insert(START, Cell::Open);
+        maze.backtrack(&mut droid, START).unwrap();
+
+        assert_eq!(Point { x: 1, y: 1 }, droid.at);
+        assert_eq!(Some(Point { x: 1, y: 2 }), maze.oxygen());
+        assert_eq!(Some(3), maze.shortest_path());
+        assert_eq!(Some(4), maze.fill_time());
+        assert_eq!(" ##   \n#..## \n#.#..#\n#.O.# \n ###  \n", maze.render());
+    }
+
+    #[test]
+    fn cloning_finds_the_same_maze() {
+        let droid = MapDroid::new(EXAMPLE);
+
+        assert_eq!(
+            explore(droid.clone(), Strategy::Backtrack),
+            explore(droid, Strategy::Clone)
+        );
+    }
+
+    #[test]
+    fn intcode_droid() {
+        // A corridor three cells long running east, with the oxygen system
+        // at the far end.
+        let program = assemble(
+            "loop:   in [cmd]
+                     eq [cmd], #4, [t]
+                     jt [t], #east
+                     eq [cmd], #3, [t]
+                     jt [t], #west
+                     out #0
+                     jt #1, #loop
+             east:   eq [pos], #2, [t]
+                     jt [t], #wall
+                     add [pos], #1, [pos]
+                     jt #1, #report
+             west:   jf [pos], #wall
+                     add [pos], #-1, [pos]
+             report: eq [pos], #2, [t]
+                     add [t], #1, [t]
+                     out [t]
+                     jt #1, #loop
+             wall:   out #0
+                     jt #1, #loop
+             cmd:    db 0
+             t:      db 0
+             pos:    db 0",
+        )
+        .unwrap();
+
+        for &strategy in &[Strategy::Backtrack, Strategy::Clone] {
+            let maze =
+                explore(IntcodeDroid::new(program.clone()), strategy).unwrap();
+
+            assert_eq!(Some(Point { x: 2, y: 0 }), maze.oxygen());
+            assert_eq!(Some(2), maze.shortest_path());
+            assert_eq!(Some(2), maze.fill_time());
+        }
+    }
+
+    #[test]
+    fn bad_status() {
+        let program = assemble("in [x]\n out #7\n x: db 0").unwrap();
+
+        assert_eq!(
+            Err(Error::BadStatus { status: 7 }),
+            explore(IntcodeDroid::new(program), Strategy::Clone)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+pub use crate::crossed_wires::Point;
+
+/// Which way something on the grid is facing. Grids are in screen
+/// coordinates: y grows downwards, so north is y - 1.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Heading {
+    North,
+    East,
+    South,
+    West,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Turn {
+    Left,
+    Right,
+}
+
+impl Heading {
+    pub const ALL: [Heading; 4] =
+        [Heading::North, Heading::East, Heading::South, Heading::West];
+
+    pub fn turn(self, turn: Turn) -> Heading {
+        use Heading::*;
+
+        match (self, turn) {
+            (North, Turn::Left) | (South, Turn::Right) => West,
+            (North, Turn::Right) | (South, Turn::Left) => East,
+            (East, Turn::Left) | (West, Turn::Right) => North,
+            (East, Turn::Right) | (West, Turn::Left) => South,
+        }
+    }
+
+    pub fn reverse(self) -> Heading {
+        self.turn(Turn::Left).turn(Turn::Left)
+    }
+
+    /// The point one step away from `from` in this direction.
+    pub fn step(self, from: Point) -> Point {
+        let Point { x, y } = from;
+
+        match self {
+            Heading::North => Point { x, y: y - 1 },
+            Heading::East => Point { x: x + 1, y },
+            Heading::South => Point { x, y: y + 1 },
+            Heading::West => Point { x: x - 1, y },
+        }
+    }
+}
+
+/// The four points next to `point`.
+pub fn neighbours(point: Point) -> impl Iterator<Item = Point> {
+    Heading::ALL.iter().map(move |heading| heading.step(point))
+}
+
+/// The top-left and bottom-right corners of the smallest box around
+/// `points`.
+pub fn bounds<'a>(
+    points: impl IntoIterator<Item = &'a Point>,
+) -> Option<(Point, Point)> {
+    points.into_iter().fold(None, |bounds, p| {
+        let (min, max) = bounds.unwrap_or((*p, *p));
+
+        Some((
+            Point {
+                x: min.x.min(p.x),
+                y: min.y.min(p.y),
+            },
+            Point {
+                x: max.x.max(p.x),
+                y: max.y.max(p.y),
+            },
+        ))
+    })
+}
+
+/// Draws every cell in the bounding box of `cells`, one line per row.
+/// `draw` is given `None` for points with no cell.
+pub fn render<C>(
+    cells: &HashMap<Point, C>,
+    draw: impl Fn(Option<&C>) -> char,
+) -> String {
+    let (min, max) = match bounds(cells.keys()) {
+        Some(bounds) => bounds,
+        None => return String::new(),
+    };
+
+    let mut out = String::new();
+    for y in min.y..=max.y {
+        out.extend((min.x..=max.x).map(|x| draw(cells.get(&Point { x, y }))));
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turning() {
+        assert_eq!(Heading::West, Heading::North.turn(Turn::Left));
+        assert_eq!(Heading::North, Heading::West.turn(Turn::Right));
+        assert_eq!(Heading::South, Heading::North.reverse());
+        assert_eq!(
+            Point { x: 0, y: -1 },
+            Heading::North.step(Point { x: 0, y: 0 })
+        );
+    }
+
+    #[test]
+    fn rendering() {
+        let cells: HashMap<_, _> =
+            vec![(Point { x: -1, y: 0 }, 'a'), (Point { x: 1, y: 1 }, 'b')]
+                .into_iter()
+                .collect();
+
+        assert_eq!("a..\n..b\n", render(&cells, |c| *c.unwrap_or(&'.')));
+    }
+}
@@ -1,31 +1,53 @@
+use std::collections::VecDeque;
 use std::convert::TryFrom;
+use std::fmt;
+use std::num::ParseIntError;
+
+use snafu::{ResultExt, Snafu};
+
+pub mod ascii;
+pub mod asm;
+mod cache;
+pub mod compile;
+mod cycle;
+pub mod debugger;
+pub mod disasm;
+mod memory;
+pub mod snapshot;
+pub mod sweep;
+pub mod symbolic;
+pub mod trace;
+
+use cache::DecodeCache;
+use cycle::CycleDetector;
+pub use memory::Memory;
+use snapshot::Snapshot;
+use trace::{Event, Tracer, Write};
 
-type Memory = Vec<i64>;
 type Address = usize;
 
-const DEBUG_ON: bool = false;
-
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum Parameter {
     Position(Address),
     Immediate(i64),
+    Relative(i64),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum Op {
     Halt,
     Add {
         s1: Parameter,
         s2: Parameter,
-        dest: Address,
+        dest: Parameter,
     },
     Mul {
         s1: Parameter,
         s2: Parameter,
-        dest: Address,
+        dest: Parameter,
     },
     Input {
-        dest: Address,
+        dest: Parameter,
     },
     Output {
         src: Parameter,
@@ -41,19 +63,75 @@ enum Op {
     LessThan {
         s1: Parameter,
         s2: Parameter,
-        dest: Address,
+        dest: Parameter,
     },
     Equals {
         s1: Parameter,
         s2: Parameter,
-        dest: Address,
+        dest: Parameter,
     },
+    AdjustRelativeBase {
+        offset: Parameter,
+    },
+}
+
+impl Op {
+    /// Number of words the instruction occupies, including the opcode.
+    fn size(&self) -> usize {
+        use Op::*;
+
+        match self {
+            Halt => 1,
+            Input { .. } | Output { .. } | AdjustRelativeBase { .. } => 2,
+            JumpIfTrue { .. } | JumpIfFalse { .. } => 3,
+            Add { .. } | Mul { .. } | LessThan { .. } | Equals { .. } => 4,
+        }
+    }
+
+    /// The parameters the instruction reads from.
+    fn sources(&self) -> Vec<&Parameter> {
+        use Op::*;
+
+        match self {
+            Halt | Input { .. } => vec![],
+            Output { src } => vec![src],
+            AdjustRelativeBase { offset } => vec![offset],
+            JumpIfTrue { cmp, dest } | JumpIfFalse { cmp, dest } => {
+                vec![cmp, dest]
+            }
+            Add { s1, s2, .. }
+            | Mul { s1, s2, .. }
+            | LessThan { s1, s2, .. }
+            | Equals { s1, s2, .. } => vec![s1, s2],
+        }
+    }
+
+    /// The parameter the instruction writes to, if any.
+    fn dest(&self) -> Option<&Parameter> {
+        use Op::*;
+
+        match self {
+            Add { dest, .. }
+            | Mul { dest, .. }
+            | LessThan { dest, .. }
+            | Equals { dest, .. }
+            | Input { dest } => Some(dest),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    Position,
+    Immediate,
+    Relative,
 }
 
 #[derive(Debug, PartialEq)]
 struct InstructionSpec {
     opcode: u8,
-    param_is_immediate: [bool; 3],
+    param_modes: [Mode; 3],
 }
 
 #[derive(Debug, PartialEq)]
@@ -63,211 +141,627 @@ enum Jump {
     Halt,
 }
 
-#[derive(Debug, PartialEq)]
-enum Error {
-    UnknownOpcode { spec: InstructionSpec },
+/// Why an instruction couldn't be decoded or executed.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Reason {
+    InstructionOutOfRange,
+    UnknownOpcode(u8),
+    UnknownMode(u8),
+    ImmediateDestination,
+    NegativeAddress(i64),
+    InvalidJump(i64),
+    MissingInput,
+    StepLimit(usize),
+    Cycle,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Reason::*;
+
+        match self {
+            InstructionOutOfRange => write!(f, "instruction out of range"),
+            UnknownOpcode(opcode) => write!(f, "unknown opcode {}", opcode),
+            UnknownMode(mode) => write!(f, "unknown parameter mode {}", mode),
+            ImmediateDestination => {
+                write!(f, "destination in immediate mode")
+            }
+            NegativeAddress(address) => {
+                write!(f, "negative address {}", address)
+            }
+            InvalidJump(dest) => write!(f, "jump to invalid address {}", dest),
+            MissingInput => write!(f, "expected an input"),
+            StepLimit(limit) => write!(f, "step limit of {} reached", limit),
+            Cycle => write!(f, "stuck in an infinite loop"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Failed to parse {:?}: {}", text, source))]
+    Parse { text: String, source: ParseIntError },
+    #[snafu(display(
+        "Failure at ip {} (instruction {}): {}",
+        ip,
+        instruction,
+        reason
+    ))]
+    Execution {
+        ip: Address,
+        instruction: i64,
+        reason: Reason,
+    },
+}
+
+/// Somewhere for the values emitted by output instructions to go.
+pub trait Sink {
+    fn emit(&mut self, value: i64);
+}
+
+impl Sink for Vec<i64> {
+    fn emit(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+impl<S: Sink + ?Sized> Sink for &mut S {
+    fn emit(&mut self, value: i64) {
+        (**self).emit(value);
+    }
+}
+
+/// Writes each emitted value as an `Output: N` line.
+pub struct TextSink<W>(pub W);
+
+impl<W: std::io::Write> Sink for TextSink<W> {
+    fn emit(&mut self, value: i64) {
+        writeln!(self.0, "Output: {}", value).unwrap();
+    }
 }
 
-pub fn load_program(text: &str) -> Memory {
+pub fn load_program(text: &str) -> Result<Memory, Error> {
     text.trim()
         .split(',')
-        .map(|s| {
-            s.parse::<i64>()
-                .unwrap_or_else(|_| panic!("Failed to parse: {:?}", s))
-        })
+        .map(|s| s.trim().parse::<i64>().context(Parse { text: s }))
         .collect()
 }
 
-fn parse_instruction_spec(int: u16) -> InstructionSpec {
+/// Writes memory out in the comma separated format `load_program` reads.
+pub fn format_program(mem: &Memory) -> String {
+    mem.to_vec()
+        .iter()
+        .map(|n| n.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn parse_instruction_spec(int: u16) -> Result<InstructionSpec, Reason> {
     let mut remaining = int;
 
-    let ones = u8::try_from(remaining % 10).unwrap();
-    remaining /= 10;
-    let tens = u8::try_from(remaining % 10).unwrap();
-    remaining /= 10;
+    let opcode = u8::try_from(remaining % 100).unwrap();
+    remaining /= 100;
 
-    InstructionSpec {
-        opcode: u8::try_from((tens * 10u8) + ones).unwrap(),
-        param_is_immediate: [
-            (remaining & 1) == 1,
-            (remaining & 10) == 10,
-            (remaining & 100) == 100,
-        ],
+    let mut param_modes = [Mode::Position; 3];
+    for mode in param_modes.iter_mut() {
+        *mode = match remaining % 10 {
+            0 => Mode::Position,
+            1 => Mode::Immediate,
+            2 => Mode::Relative,
+            m => return Err(Reason::UnknownMode(u8::try_from(m).unwrap())),
+        };
+        remaining /= 10;
     }
+
+    Ok(InstructionSpec {
+        opcode,
+        param_modes,
+    })
 }
 
-fn build_op(spec: InstructionSpec, slice: &[i64]) -> Result<Op, Error> {
+fn build_op(spec: InstructionSpec, slice: &[i64]) -> Result<Op, Reason> {
     use Op::*;
 
-    let build_param = |n| {
-        if spec.param_is_immediate[n] {
-            Parameter::Immediate(slice[n])
-        } else {
-            Parameter::Position(
-                Address::try_from(slice[n])
-                    .expect("Positions must be usize convertible"),
-            )
-        }
+    let build_param = |n: usize| match spec.param_modes[n] {
+        Mode::Position => Address::try_from(slice[n])
+            .map(Parameter::Position)
+            .map_err(|_| Reason::NegativeAddress(slice[n])),
+        Mode::Immediate => Ok(Parameter::Immediate(slice[n])),
+        Mode::Relative => Ok(Parameter::Relative(slice[n])),
     };
 
-    let address_from = |n: usize| {
-        Address::try_from(slice[n])
-            .unwrap_or_else(|_| panic!("{} must be an Address", slice[n]))
+    let address_from = |n: usize| match spec.param_modes[n] {
+        Mode::Immediate => Err(Reason::ImmediateDestination),
+        _ => build_param(n),
     };
 
     let op = match spec.opcode {
         1 => Add {
-            s1: build_param(0),
-            s2: build_param(1),
-            dest: address_from(2),
+            s1: build_param(0)?,
+            s2: build_param(1)?,
+            dest: address_from(2)?,
         },
         2 => Mul {
-            s1: build_param(0),
-            s2: build_param(1),
-            dest: address_from(2),
+            s1: build_param(0)?,
+            s2: build_param(1)?,
+            dest: address_from(2)?,
         },
         3 => Input {
-            dest: address_from(0),
+            dest: address_from(0)?,
         },
         4 => Output {
-            src: build_param(0),
+            src: build_param(0)?,
         },
         5 => JumpIfTrue {
-            cmp: build_param(0),
-            dest: build_param(1),
+            cmp: build_param(0)?,
+            dest: build_param(1)?,
         },
         6 => JumpIfFalse {
-            cmp: build_param(0),
-            dest: build_param(1),
+            cmp: build_param(0)?,
+            dest: build_param(1)?,
         },
         7 => LessThan {
-            s1: build_param(0),
-            s2: build_param(1),
-            dest: address_from(2),
+            s1: build_param(0)?,
+            s2: build_param(1)?,
+            dest: address_from(2)?,
         },
         8 => Equals {
-            s1: build_param(0),
-            s2: build_param(1),
-            dest: address_from(2),
+            s1: build_param(0)?,
+            s2: build_param(1)?,
+            dest: address_from(2)?,
+        },
+        9 => AdjustRelativeBase {
+            offset: build_param(0)?,
         },
         99 => Halt,
-        _ => return Err(Error::UnknownOpcode { spec }),
+        opcode => return Err(Reason::UnknownOpcode(opcode)),
     };
 
     Ok(op)
 }
 
-fn next_op(mem: &[i64]) -> Result<Op, Error> {
-    let instruction = u16::try_from(mem[0])
-        .unwrap_or_else(|_| panic!("Instruction too large: {}", mem[0]));
+fn next_op(mem: &[i64]) -> Result<Op, Reason> {
+    let instruction =
+        u16::try_from(mem[0]).map_err(|_| Reason::InstructionOutOfRange)?;
 
-    build_op(parse_instruction_spec(instruction), &mem[1..])
+    build_op(parse_instruction_spec(instruction)?, &mem[1..])
 }
 
-fn jump_if(dest: Address, f: impl Fn() -> bool) -> Jump {
-    if f() {
-        Jump::Absolute(dest)
+fn jump_if(condition: bool, dest: i64) -> Result<Jump, Reason> {
+    if condition {
+        Address::try_from(dest)
+            .map(Jump::Absolute)
+            .map_err(|_| Reason::InvalidJump(dest))
     } else {
-        Jump::Relative(3)
+        Ok(Jump::Relative(3))
+    }
+}
+
+fn address_of(p: &Parameter, relative_base: i64) -> Result<Address, Reason> {
+    match p {
+        Parameter::Position(pos) => Ok(*pos),
+        Parameter::Relative(offset) => {
+            Address::try_from(relative_base + offset)
+                .map_err(|_| Reason::NegativeAddress(relative_base + offset))
+        }
+        Parameter::Immediate(_) => Err(Reason::ImmediateDestination),
+    }
+}
+
+fn read(
+    p: &Parameter,
+    mem: &Memory,
+    relative_base: i64,
+) -> Result<i64, Reason> {
+    match p {
+        Parameter::Immediate(value) => Ok(*value),
+        _ => Ok(mem[address_of(p, relative_base)?]),
     }
 }
 
 fn apply_op<'a>(
     op: &Op,
     mem: &mut Memory,
+    relative_base: &mut i64,
     inputs: &mut impl Iterator<Item = &'a i64>,
-    mut output: impl std::io::Write,
-) -> Jump {
+    mut output: impl Sink,
+) -> Result<Jump, Reason> {
     use Op::*;
 
-    let value_of = |p: &Parameter| match p {
-        Parameter::Position(pos) => mem[*pos],
-        Parameter::Immediate(value) => *value,
-    };
+    let base = *relative_base;
+    let addr = |p: &Parameter| address_of(p, base);
+    let value_of = |p: &Parameter| read(p, mem, base);
 
     match op {
         Halt => (),
-        Add { s1, s2, dest } => mem[*dest] = value_of(s1) + value_of(s2),
-        Mul { s1, s2, dest } => mem[*dest] = value_of(s1) * value_of(s2),
-        Input { dest } => {
-            mem[*dest] = inputs.next().copied().expect("Expected an input")
+        Add { s1, s2, dest } => {
+            let value = value_of(s1)? + value_of(s2)?;
+            mem[addr(dest)?] = value
+        }
+        Mul { s1, s2, dest } => {
+            let value = value_of(s1)? * value_of(s2)?;
+            mem[addr(dest)?] = value
         }
-        Output { src } => {
-            writeln!(output, "Output: {}", value_of(src)).unwrap();
+        Input { dest } => {
+            let value = inputs.next().copied().ok_or(Reason::MissingInput)?;
+            mem[addr(dest)?] = value
         }
+        Output { src } => output.emit(value_of(src)?),
         JumpIfTrue { cmp, dest } => {
-            return jump_if(usize::try_from(value_of(dest)).unwrap(), || {
-                value_of(cmp) != 0
-            })
+            return jump_if(value_of(cmp)? != 0, value_of(dest)?)
         }
         JumpIfFalse { cmp, dest } => {
-            return jump_if(usize::try_from(value_of(dest)).unwrap(), || {
-                value_of(cmp) == 0
-            })
+            return jump_if(value_of(cmp)? == 0, value_of(dest)?)
         }
         LessThan { s1, s2, dest } => {
-            mem[*dest] = if value_of(s1) < value_of(s2) { 1 } else { 0 }
+            let value = if value_of(s1)? < value_of(s2)? { 1 } else { 0 };
+            mem[addr(dest)?] = value
         }
         Equals { s1, s2, dest } => {
-            mem[*dest] = if value_of(s1) == value_of(s2) { 1 } else { 0 }
+            let value = if value_of(s1)? == value_of(s2)? { 1 } else { 0 };
+            mem[addr(dest)?] = value
         }
+        AdjustRelativeBase { offset } => *relative_base += value_of(offset)?,
     }
 
-    match &op {
-        Add { .. } | Mul { .. } | LessThan { .. } | Equals { .. } => {
-            Jump::Relative(4)
-        }
-        Input { .. } | Output { .. } => Jump::Relative(2),
-        Halt => Jump::Halt,
-        _ => panic!("Unknown op: {:?}", op),
+    match op {
+        Halt => Ok(Jump::Halt),
+        _ => Ok(Jump::Relative(op.size())),
     }
 }
 
-fn write_debug(mem: &Memory, next_op: &Op, mut output: impl std::io::Write) {
-    writeln!(output, "Mem:").unwrap();
+/// Why a `Machine` stopped running.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum State {
+    /// An input instruction was reached with nothing queued. Push an input
+    /// and `run` again to resume from the same instruction.
+    NeedsInput,
+    Output(i64),
+    Halted,
+}
+
+/// An intcode interpreter that can be paused and resumed.
+///
+/// Unlike `execute`, a machine keeps its ip, relative base and memory
+/// between calls to `run`, which makes it possible to compute inputs from
+/// earlier outputs or to wire several machines together.
+#[derive(Debug, Clone)]
+pub struct Machine {
+    mem: Memory,
+    ip: Address,
+    relative_base: i64,
+    inputs: VecDeque<i64>,
+    halted: bool,
+    steps: usize,
+    cache: Option<DecodeCache>,
+    step_limit: Option<usize>,
+    cycles: Option<CycleDetector>,
+}
+
+impl Machine {
+    pub fn new(mem: Memory) -> Machine {
+        Machine {
+            mem,
+            ip: 0,
+            relative_base: 0,
+            inputs: VecDeque::new(),
+            halted: false,
+            steps: 0,
+            cache: Some(DecodeCache::default()),
+            step_limit: None,
+            cycles: None,
+        }
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.inputs.push_back(value);
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.mem
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
 
-    let mut offset = 0;
-    for line in (*mem).chunks(5).map(|c| {
-        c.iter()
-            .map(|n| n.to_string())
-            .collect::<Vec<String>>()
-            .join("\t")
-    }) {
-        writeln!(output, "{}|\t{}", offset, line).unwrap();
+        &mut self.mem
+    }
 
-        offset += 5
+    /// Turns the decoded-instruction cache on or off. It's on by default.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.cache = if enabled {
+            Some(DecodeCache::default())
+        } else {
+            None
+        };
     }
 
-    writeln!(output, "Next op: {:?}", next_op).unwrap();
-    writeln!(output, "").unwrap();
-    writeln!(output, "").unwrap();
+    /// Fails with `Reason::StepLimit` rather than executing more than
+    /// `limit` instructions in total.
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.step_limit = limit;
+    }
+
+    /// Checks for a repeated state every `interval` steps and fails with
+    /// `Reason::Cycle` when one is found. Off by default.
+    pub fn set_cycle_detection(&mut self, interval: Option<usize>) {
+        self.cycles = interval.map(CycleDetector::new);
+    }
+
+    pub fn into_memory(self) -> Memory {
+        self.mem
+    }
+
+    /// Captures the machine's state. Settings like the step limit aren't
+    /// part of it.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            ip: self.ip,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            halted: self.halted,
+            memory: self.mem.clone(),
+            inputs: self.inputs.iter().copied().collect(),
+        }
+    }
+
+    /// A machine with default settings carrying on from `snapshot`.
+    pub fn restore(snapshot: Snapshot) -> Machine {
+        Machine {
+            ip: snapshot.ip,
+            relative_base: snapshot.relative_base,
+            inputs: snapshot.inputs.into_iter().collect(),
+            halted: snapshot.halted,
+            steps: snapshot.steps,
+            ..Machine::new(snapshot.memory)
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn ip(&self) -> Address {
+        self.ip
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    fn decode(&mut self) -> Result<Op, Error> {
+        let ip = self.ip;
+        let mem = &self.mem;
+        let words = [mem[ip], mem[ip + 1], mem[ip + 2], mem[ip + 3]];
+        let op = next_op(&words).map_err(|r| self.fault(r))?;
+
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(ip, op);
+        }
+
+        Ok(op)
+    }
+
+    fn fault(&self, reason: Reason) -> Error {
+        Error::Execution {
+            ip: self.ip,
+            instruction: self.mem[self.ip],
+            reason,
+        }
+    }
+
+    /// Runs until the program produces an output, needs an input that
+    /// hasn't been pushed yet, or halts. A failing instruction leaves the
+    /// machine pointing at it.
+    pub fn run(&mut self) -> Result<State, Error> {
+        self.run_traced(&mut ())
+    }
+
+    pub fn run_traced(
+        &mut self,
+        tracer: &mut impl Tracer,
+    ) -> Result<State, Error> {
+        loop {
+            if let Some(state) = self.step_traced(tracer)? {
+                return Ok(state);
+            }
+        }
+    }
+
+    /// Executes a single instruction, returning the state if it's one that
+    /// `run` would stop for.
+    pub fn step(&mut self) -> Result<Option<State>, Error> {
+        self.step_traced(&mut ())
+    }
+
+    pub fn step_traced(
+        &mut self,
+        tracer: &mut impl Tracer,
+    ) -> Result<Option<State>, Error> {
+        use Op::*;
+
+        if self.halted {
+            return Ok(Some(State::Halted));
+        }
+
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err(self.fault(Reason::StepLimit(limit)));
+            }
+        }
+
+        let ip = self.ip;
+        let op = match self.cache.as_ref().and_then(|c| c.get(ip)) {
+            Some(op) => op,
+            None => self.decode()?,
+        };
+        let mem = &self.mem;
+
+        let input = match op {
+            Input { .. } => match self.inputs.front() {
+                Some(&value) => Some(value),
+                None => return Ok(Some(State::NeedsInput)),
+            },
+            _ => None,
+        };
+
+        let base = self.relative_base;
+        if let Some(cycles) = self.cycles.as_mut() {
+            if input.is_some() {
+                cycles.reset();
+            } else if cycles.check(self.steps, ip, base, mem) {
+                return Err(self.fault(Reason::Cycle));
+            }
+        }
+
+        // Failed instructions aren't recorded, and an operand that can't be
+        // read means this one is about to fail.
+        let mut event = if tracer.enabled() {
+            let dest = op.dest().and_then(|p| address_of(p, base).ok());
+            let operands: Result<Vec<i64>, _> =
+                op.sources().iter().map(|p| read(p, mem, base)).collect();
+
+            operands.ok().map(|operands| Event {
+                step: self.steps,
+                ip,
+                op: op.to_string(),
+                operands,
+                write: dest.map(|address| Write {
+                    address,
+                    old: mem[address],
+                    new: mem[address],
+                }),
+                input,
+                output: None,
+            })
+        } else {
+            None
+        };
+
+        let mut emitted = vec![];
+
+        let jump = apply_op(
+            &op,
+            &mut self.mem,
+            &mut self.relative_base,
+            &mut input.iter(),
+            &mut emitted,
+        )
+        .map_err(|r| self.fault(r))?;
+
+        // Only consumed once the instruction has succeeded, so a failed one
+        // can be retried.
+        if input.is_some() {
+            self.inputs.pop_front();
+        }
+        self.steps += 1;
+
+        if let (Some(cache), Some(dest)) = (self.cache.as_mut(), op.dest()) {
+            if let Ok(address) = address_of(dest, base) {
+                cache.invalidate(address);
+            }
+        }
+
+        if let Some(event) = event.as_mut() {
+            if let Some(write) = event.write.as_mut() {
+                write.new = self.mem[write.address];
+            }
+            event.output = emitted.last().copied();
+            tracer.record(event);
+        }
+
+        match jump {
+            Jump::Relative(offset) => self.ip += offset,
+            Jump::Absolute(address) => self.ip = address,
+            Jump::Halt => {
+                self.halted = true;
+                return Ok(Some(State::Halted));
+            }
+        }
+
+        Ok(emitted.pop().map(State::Output))
+    }
 }
 
 pub fn execute<'a>(
     mem: &mut Memory,
     inputs: &mut impl Iterator<Item = &'a i64>,
-    mut output: impl std::io::Write,
-) {
-    let mut ip = 0;
+    output: impl Sink,
+) -> Result<(), Error> {
+    execute_traced(mem, inputs, output, &mut ())
+}
 
-    loop {
-        let op = next_op(&mem[ip..]).unwrap_or_else(|e| {
-            panic!("Failure at ip: {}\nFailure: {:?}\nMemory: {:?}", ip, e, mem)
-        });
+/// Like `execute`, but reports every instruction to `tracer`.
+pub fn execute_traced<'a>(
+    mem: &mut Memory,
+    inputs: &mut impl Iterator<Item = &'a i64>,
+    mut output: impl Sink,
+    tracer: &mut impl Tracer,
+) -> Result<(), Error> {
+    let mut machine = Machine::new(std::mem::take(mem));
+    let result = drive(&mut machine, inputs, &mut output, tracer);
 
-        if DEBUG_ON {
-            write_debug(mem, &op, &mut output);
-        }
+    *mem = machine.into_memory();
 
-        ip = match apply_op(&op, mem, inputs, &mut output) {
-            Jump::Relative(offset) => ip + offset,
-            Jump::Absolute(address) => address,
-            Jump::Halt => break,
-        };
+    result
+}
+
+/// Guards against programs that never halt.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    /// Maximum number of instructions to execute.
+    pub steps: Option<usize>,
+    /// How often to check for a repeated state, in steps.
+    pub cycle_interval: Option<usize>,
+}
+
+/// Like `execute`, but gives up with `Reason::StepLimit` or `Reason::Cycle`
+/// instead of running forever.
+pub fn execute_limited<'a>(
+    mem: &mut Memory,
+    inputs: &mut impl Iterator<Item = &'a i64>,
+    mut output: impl Sink,
+    limits: Limits,
+) -> Result<(), Error> {
+    let mut machine = Machine::new(std::mem::take(mem));
+    machine.set_step_limit(limits.steps);
+    machine.set_cycle_detection(limits.cycle_interval);
+
+    let result = drive(&mut machine, inputs, &mut output, &mut ());
+
+    *mem = machine.into_memory();
+
+    result
+}
+
+/// Runs a machine to completion, feeding it from `inputs`.
+fn drive<'a>(
+    machine: &mut Machine,
+    inputs: &mut impl Iterator<Item = &'a i64>,
+    mut output: impl Sink,
+    tracer: &mut impl Tracer,
+) -> Result<(), Error> {
+    loop {
+        match machine.run_traced(tracer)? {
+            State::NeedsInput => match inputs.next() {
+                Some(value) => machine.push_input(*value),
+                None => return Err(machine.fault(Reason::MissingInput)),
+            },
+            State::Output(value) => output.emit(value),
+            State::Halted => return Ok(()),
+        }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
     use super::*;
 
@@ -275,7 +769,7 @@ mod tests {
         mem: &mut Memory,
         inputs: &mut impl Iterator<Item = &'a i64>,
     ) {
-        execute(mem, inputs, &mut std::io::stdout())
+        execute(mem, inputs, TextSink(std::io::stdout())).unwrap()
     }
 
     fn apply_to_stdout<'a>(
@@ -283,19 +777,37 @@ mod tests {
         mem: &mut Memory,
         inputs: &mut impl Iterator<Item = &'a i64>,
     ) -> Jump {
-        apply_op(op, mem, inputs, &mut std::io::stdout())
+        apply_op(op, mem, &mut 0, inputs, TextSink(std::io::stdout())).unwrap()
     }
 
     #[test]
     fn loading() {
-        assert_eq!(vec![1, 2, 3, 4], load_program("1,2,3,4"))
+        assert_eq!(Ok(vec![1, 2, 3, 4].into()), load_program("1,2,3,4"))
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!("1,-2,3,4", format_program(&vec![1, -2, 3, 4].into()))
     }
 
     #[test]
     fn parsing() {
-        let spec = parse_instruction_spec(99);
+        let spec = parse_instruction_spec(99).unwrap();
         assert_eq!(99, spec.opcode);
-        assert_eq!([false, false, false], spec.param_is_immediate);
+        assert_eq!(
+            [Mode::Position, Mode::Position, Mode::Position],
+            spec.param_modes
+        );
+    }
+
+    #[test]
+    fn parsing_modes() {
+        let spec = parse_instruction_spec(21002).unwrap();
+        assert_eq!(2, spec.opcode);
+        assert_eq!(
+            [Mode::Position, Mode::Immediate, Mode::Relative],
+            spec.param_modes
+        );
     }
 
     #[test]
@@ -309,7 +821,7 @@ mod tests {
             Ok(Op::Add {
                 s1: Parameter::Position(9),
                 s2: Parameter::Position(10),
-                dest: 3
+                dest: Parameter::Position(3)
             }),
             next_op(&[1, 9, 10, 3])
         );
@@ -321,7 +833,7 @@ mod tests {
             Ok(Op::Mul {
                 s1: Parameter::Position(3),
                 s2: Parameter::Position(11),
-                dest: 0
+                dest: Parameter::Position(0)
             }),
             next_op(&[2, 3, 11, 0])
         )
@@ -355,7 +867,7 @@ mod tests {
             Ok(Op::LessThan {
                 s1: Parameter::Immediate(3),
                 s2: Parameter::Immediate(4),
-                dest: 0
+                dest: Parameter::Position(0)
             }),
             next_op(&[1107, 3, 4, 0])
         )
@@ -367,7 +879,7 @@ mod tests {
             Ok(Op::Equals {
                 s1: Parameter::Immediate(3),
                 s2: Parameter::Immediate(4),
-                dest: 0
+                dest: Parameter::Position(0)
             }),
             next_op(&[1108, 3, 4, 0])
         )
@@ -375,7 +887,8 @@ mod tests {
 
     #[test]
     fn apply_add() {
-        let mut mem: Memory = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let mut mem =
+            Memory::from(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
         let inputs = vec![];
 
         assert_eq!(
@@ -384,7 +897,7 @@ mod tests {
                 &Op::Add {
                     s1: Parameter::Position(9),
                     s2: Parameter::Position(10),
-                    dest: 3,
+                    dest: Parameter::Position(3),
                 },
                 &mut mem,
                 &mut inputs.iter(),
@@ -396,7 +909,8 @@ mod tests {
 
     #[test]
     fn apply_mul() {
-        let mut mem: Memory = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let mut mem =
+            Memory::from(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
         let inputs = vec![];
 
         assert_eq!(
@@ -405,7 +919,7 @@ mod tests {
                 &Op::Mul {
                     s1: Parameter::Position(3),
                     s2: Parameter::Position(11),
-                    dest: 0,
+                    dest: Parameter::Position(0),
                 },
                 &mut mem,
                 &mut inputs.iter(),
@@ -417,7 +931,8 @@ mod tests {
 
     #[test]
     fn apply_halt() {
-        let mut mem: Memory = vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50];
+        let mut mem =
+            Memory::from(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
         let inputs = vec![];
 
         assert_eq!(
@@ -430,7 +945,7 @@ mod tests {
 
     #[test]
     fn apply_jump_if_true() {
-        let mut mem = vec![5, 0, 40];
+        let mut mem = Memory::from(vec![5, 0, 40]);
         let inputs = vec![];
 
         assert_eq!(
@@ -460,7 +975,7 @@ mod tests {
 
     #[test]
     fn apply_jump_if_false() {
-        let mut mem = vec![6, 0, 40];
+        let mut mem = Memory::from(vec![6, 0, 40]);
         let inputs = vec![];
 
         assert_eq!(
@@ -490,7 +1005,7 @@ mod tests {
 
     #[test]
     fn apply_less_than() {
-        let mut mem = vec![7];
+        let mut mem = Memory::from(vec![7]);
         let inputs = vec![];
 
         assert_eq!(
@@ -499,7 +1014,7 @@ mod tests {
                 &Op::LessThan {
                     s1: Parameter::Immediate(3),
                     s2: Parameter::Immediate(4),
-                    dest: 0
+                    dest: Parameter::Position(0)
                 },
                 &mut mem,
                 &mut inputs.iter()
@@ -514,7 +1029,7 @@ mod tests {
                 &Op::LessThan {
                     s1: Parameter::Immediate(3),
                     s2: Parameter::Immediate(2),
-                    dest: 0
+                    dest: Parameter::Position(0)
                 },
                 &mut mem,
                 &mut inputs.iter()
@@ -526,7 +1041,7 @@ mod tests {
 
     #[test]
     fn apply_equals() {
-        let mut mem = vec![7];
+        let mut mem = Memory::from(vec![7]);
         let inputs = vec![];
 
         assert_eq!(
@@ -535,7 +1050,7 @@ mod tests {
                 &Op::Equals {
                     s1: Parameter::Immediate(3),
                     s2: Parameter::Immediate(4),
-                    dest: 0
+                    dest: Parameter::Position(0)
                 },
                 &mut mem,
                 &mut inputs.iter()
@@ -550,7 +1065,7 @@ mod tests {
                 &Op::Equals {
                     s1: Parameter::Immediate(3),
                     s2: Parameter::Immediate(3),
-                    dest: 0
+                    dest: Parameter::Position(0)
                 },
                 &mut mem,
                 &mut inputs.iter()
@@ -562,7 +1077,7 @@ mod tests {
 
     #[test]
     fn execute_add() {
-        let mut mem = vec![1, 0, 0, 0, 99];
+        let mut mem = Memory::from(vec![1, 0, 0, 0, 99]);
         let input = vec![];
 
         execute_to_stdout(&mut mem, &mut input.iter());
@@ -571,7 +1086,7 @@ mod tests {
 
     #[test]
     fn execute_mul() {
-        let mut mem = vec![2, 3, 0, 3, 99];
+        let mut mem = Memory::from(vec![2, 3, 0, 3, 99]);
         let input = vec![];
 
         execute_to_stdout(&mut mem, &mut input.iter());
@@ -580,7 +1095,7 @@ mod tests {
 
     #[test]
     fn execute_with_trailing_data() {
-        let mut mem = vec![2, 4, 4, 5, 99, 0];
+        let mut mem = Memory::from(vec![2, 4, 4, 5, 99, 0]);
         let input = vec![];
 
         execute_to_stdout(&mut mem, &mut input.iter());
@@ -589,9 +1104,387 @@ mod tests {
 
     #[test]
     fn execute_instructions_modified() {
-        let mut mem = vec![1, 1, 1, 4, 99, 5, 6, 0, 99];
+        let mut mem = Memory::from(vec![1, 1, 1, 4, 99, 5, 6, 0, 99]);
         let input = vec![];
         execute_to_stdout(&mut mem, &mut input.iter());
         assert_eq!(mem, vec![30, 1, 1, 4, 2, 5, 6, 0, 99]);
     }
+
+    #[test]
+    fn execute_past_the_end() {
+        let mut mem = Memory::from(vec![1101, 1, 2, 1000, 4, 2000, 99]);
+        let input: [i64; 0] = [];
+
+        execute_to_stdout(&mut mem, &mut input.iter());
+        assert_eq!(3, mem[1000]);
+        assert_eq!(1001, mem.len());
+    }
+
+    fn outputs_of(mut mem: Memory) -> Vec<i64> {
+        let input: [i64; 0] = [];
+        let mut output = vec![];
+
+        execute(&mut mem, &mut input.iter(), &mut output).unwrap();
+
+        output
+    }
+
+    #[test]
+    fn text_sink() {
+        let mut text = vec![];
+
+        TextSink(&mut text).emit(42);
+        TextSink(&mut text).emit(-7);
+
+        assert_eq!(
+            "Output: 42\nOutput: -7\n",
+            String::from_utf8(text).unwrap()
+        );
+    }
+
+    #[test]
+    fn building_adjust_relative_base() {
+        assert_eq!(
+            Ok(Op::AdjustRelativeBase {
+                offset: Parameter::Relative(-7)
+            }),
+            next_op(&[209, -7])
+        )
+    }
+
+    #[test]
+    fn building_relative_dest() {
+        assert_eq!(
+            Ok(Op::Add {
+                s1: Parameter::Immediate(1),
+                s2: Parameter::Position(2),
+                dest: Parameter::Relative(3)
+            }),
+            next_op(&[20101, 1, 2, 3])
+        )
+    }
+
+    #[test]
+    fn apply_adjust_relative_base() {
+        let mut mem = Memory::from(vec![109, 19, 204, -34]);
+        let mut relative_base = 2000;
+        let inputs: [i64; 0] = [];
+
+        assert_eq!(
+            Ok(Jump::Relative(2)),
+            apply_op(
+                &Op::AdjustRelativeBase {
+                    offset: Parameter::Immediate(19)
+                },
+                &mut mem,
+                &mut relative_base,
+                &mut inputs.iter(),
+                TextSink(std::io::stdout())
+            )
+        );
+
+        assert_eq!(2019, relative_base)
+    }
+
+    #[test]
+    fn execute_relative_output() {
+        let mut mem = Memory::from(vec![109, 5, 204, 1, 99, 0, 42]);
+
+        assert_eq!(vec![42], outputs_of(mem.clone()));
+
+        mem[6] = 7;
+        assert_eq!(vec![7], outputs_of(mem));
+    }
+
+    #[test]
+    fn execute_quine() {
+        let program = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101,
+            0, 99,
+        ];
+
+        assert_eq!(program.clone(), outputs_of(program.into()));
+    }
+
+    #[test]
+    fn execute_large_multiply() {
+        let mem = Memory::from(vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0]);
+
+        assert_eq!(vec![1219070632396864], outputs_of(mem));
+    }
+
+    #[test]
+    fn execute_large_output() {
+        let mem = Memory::from(vec![104, 1125899906842624, 99]);
+
+        assert_eq!(vec![1125899906842624], outputs_of(mem));
+    }
+
+    #[test]
+    fn machine_pauses_for_input() {
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        let mut machine = Machine::new(program.into());
+
+        assert_eq!(Ok(State::NeedsInput), machine.run());
+        assert_eq!(Ok(State::NeedsInput), machine.run());
+
+        machine.push_input(8);
+        assert_eq!(Ok(State::Output(1)), machine.run());
+        assert_eq!(Ok(State::Halted), machine.run());
+        assert_eq!(Ok(State::Halted), machine.run());
+        assert!(machine.is_halted());
+    }
+
+    #[test]
+    fn failed_input_keeps_the_value() {
+        // Stores the input at relative base - 1, then outputs address 0.
+        let program = vec![203, -1, 4, 0, 99];
+        let mut machine = Machine::new(program.into());
+        machine.push_input(7);
+
+        assert!(machine.run().is_err());
+        let mut snapshot = machine.snapshot();
+        assert_eq!(vec![7], snapshot.inputs);
+
+        snapshot.relative_base = 1;
+        let mut machine = Machine::restore(snapshot);
+        assert_eq!(Ok(State::Output(7)), machine.run());
+    }
+
+    #[test]
+    fn machine_feeds_back_outputs() {
+        // Reads a number and outputs it doubled, forever.
+        let program = vec![3, 9, 1002, 9, 2, 9, 4, 9, 1105, 1, 0];
+        let mut machine = Machine::new(Memory::from(program));
+        let mut value = 1;
+
+        for _ in 0..10 {
+            assert_eq!(Ok(State::NeedsInput), machine.run());
+            machine.push_input(value);
+            match machine.run().unwrap() {
+                State::Output(doubled) => value = doubled,
+                other => panic!("Unexpected state: {:?}", other),
+            }
+        }
+
+        assert_eq!(1024, value);
+    }
+
+    #[test]
+    fn machines_chain() {
+        // Reads a number and outputs it plus one.
+        let program = Memory::from(vec![3, 0, 1001, 0, 1, 0, 4, 0, 99]);
+        let mut machines = vec![Machine::new(program); 3];
+        let mut signal = 0;
+
+        for machine in machines.iter_mut() {
+            machine.push_input(signal);
+            match machine.run().unwrap() {
+                State::Output(value) => signal = value,
+                other => panic!("Unexpected state: {:?}", other),
+            }
+        }
+
+        assert_eq!(3, signal);
+        assert!(machines.iter_mut().all(|m| m.run() == Ok(State::Halted)));
+    }
+
+    fn error_of(program: Vec<i64>, inputs: &[i64]) -> Error {
+        let mut mem = Memory::from(program);
+        let mut output = vec![];
+
+        execute(&mut mem, &mut inputs.iter(), &mut output).unwrap_err()
+    }
+
+    #[test]
+    fn loading_garbage() {
+        assert!(matches!(
+            load_program("1,2,x,4"),
+            Err(Error::Parse { ref text, .. }) if text == "x"
+        ));
+    }
+
+    #[test]
+    fn error_unknown_opcode() {
+        assert_eq!(
+            Error::Execution {
+                ip: 4,
+                instruction: 42,
+                reason: Reason::UnknownOpcode(42)
+            },
+            error_of(vec![1101, 1, 1, 0, 42], &[])
+        );
+    }
+
+    #[test]
+    fn error_instruction_out_of_range() {
+        assert_eq!(
+            Error::Execution {
+                ip: 0,
+                instruction: -1,
+                reason: Reason::InstructionOutOfRange
+            },
+            error_of(vec![-1], &[])
+        );
+    }
+
+    #[test]
+    fn error_negative_address() {
+        assert_eq!(
+            Error::Execution {
+                ip: 2,
+                instruction: 204,
+                reason: Reason::NegativeAddress(-1)
+            },
+            error_of(vec![109, 1, 204, -2, 99], &[])
+        );
+    }
+
+    #[test]
+    fn error_immediate_destination() {
+        assert_eq!(
+            Error::Execution {
+                ip: 0,
+                instruction: 11101,
+                reason: Reason::ImmediateDestination
+            },
+            error_of(vec![11101, 1, 1, 0, 99], &[])
+        );
+    }
+
+    #[test]
+    fn error_missing_input() {
+        assert_eq!(
+            Error::Execution {
+                ip: 2,
+                instruction: 3,
+                reason: Reason::MissingInput
+            },
+            error_of(vec![3, 0, 3, 0, 99], &[7])
+        );
+    }
+
+    fn limited_error_of(
+        program: Vec<i64>,
+        inputs: &[i64],
+        limits: Limits,
+    ) -> Error {
+        let mut mem = Memory::from(program);
+        let mut output = vec![];
+
+        execute_limited(&mut mem, &mut inputs.iter(), &mut output, limits)
+            .unwrap_err()
+    }
+
+    #[test]
+    fn error_step_limit() {
+        let limits = Limits {
+            steps: Some(5),
+            cycle_interval: None,
+        };
+
+        assert_eq!(
+            Error::Execution {
+                ip: 0,
+                instruction: 1105,
+                reason: Reason::StepLimit(5)
+            },
+            limited_error_of(vec![1105, 1, 0], &[], limits)
+        );
+    }
+
+    #[test]
+    fn error_cycle() {
+        let limits = Limits {
+            steps: None,
+            cycle_interval: Some(10),
+        };
+
+        assert_eq!(
+            Error::Execution {
+                ip: 0,
+                instruction: 1105,
+                reason: Reason::Cycle
+            },
+            limited_error_of(vec![1105, 1, 0], &[], limits)
+        );
+    }
+
+    #[test]
+    fn counting_loop_is_not_a_cycle() {
+        let limits = Limits {
+            steps: Some(100),
+            cycle_interval: Some(1),
+        };
+
+        // Increments [5] forever
+        assert!(matches!(
+            limited_error_of(vec![101, 1, 5, 5, 1105, 1, 0], &[], limits),
+            Error::Execution {
+                reason: Reason::StepLimit(100),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn input_resets_cycle_detection() {
+        let limits = Limits {
+            steps: None,
+            cycle_interval: Some(1),
+        };
+        // Reads until it sees a zero
+        let mut mem = Memory::from(vec![3, 7, 1005, 7, 0, 99, 0, 0]);
+        let mut output = vec![];
+
+        execute_limited(
+            &mut mem,
+            &mut [1, 1, 1, 0].iter(),
+            &mut output,
+            limits,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn error_display() {
+        assert_eq!(
+            "Failure at ip 4 (instruction 42): unknown opcode 42",
+            error_of(vec![1101, 1, 1, 0, 42], &[]).to_string()
+        );
+    }
+
+    #[test]
+    fn machine_sees_rewritten_instructions() {
+        let program = asm::assemble(
+            "start: out #1
+                    eq [flag], #1, [t]
+                    jt [t], #end
+                    add #1, #0, [flag]
+                    add #2, #0, [start+1]
+                    jt #1, #start
+             end:   halt
+             flag:  db 0
+             t:     db 0",
+        )
+        .unwrap();
+
+        for cached in &[true, false] {
+            let mut machine = Machine::new(program.clone());
+            machine.set_decode_cache(*cached);
+
+            assert_eq!(Ok(State::Output(1)), machine.run());
+            assert_eq!(Ok(State::Output(2)), machine.run());
+            assert_eq!(Ok(State::Halted), machine.run());
+        }
+    }
+
+    #[test]
+    fn machine_sees_patched_memory() {
+        let mut machine = Machine::new(vec![104, 1, 1105, 1, 0].into());
+
+        assert_eq!(Ok(State::Output(1)), machine.run());
+        machine.memory_mut()[1] = 5;
+        assert_eq!(Ok(State::Output(5)), machine.run());
+    }
 }
@@ -0,0 +1,164 @@
+use std::convert::TryFrom;
+
+use super::{Error, Machine, Memory, State};
+
+/// Everything a program printed before it stopped.
+#[derive(Debug, Default, PartialEq)]
+pub struct Reply {
+    pub text: String,
+    /// Output values that aren't ASCII codes, in the order they came out.
+    pub numbers: Vec<i64>,
+    /// Either `State::NeedsInput` or `State::Halted`.
+    pub state: Option<State>,
+}
+
+impl Reply {
+    pub fn is_halted(&self) -> bool {
+        self.state == Some(State::Halted)
+    }
+}
+
+/// Talks to a machine in lines of text rather than individual values.
+#[derive(Debug, Clone)]
+pub struct Ascii {
+    machine: Machine,
+}
+
+impl Ascii {
+    pub fn new(mem: Memory) -> Ascii {
+        Ascii::from_machine(Machine::new(mem))
+    }
+
+    pub fn from_machine(machine: Machine) -> Ascii {
+        Ascii { machine }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn machine_mut(&mut self) -> &mut Machine {
+        &mut self.machine
+    }
+
+    pub fn into_machine(self) -> Machine {
+        self.machine
+    }
+
+    /// Queues `line` as ASCII codes followed by a newline. Any trailing
+    /// newline on `line` itself is dropped so stdin lines can be passed
+    /// straight through.
+    pub fn send_line(&mut self, line: &str) {
+        let line = line.trim_end_matches(['\n', '\r']);
+
+        for byte in line.bytes() {
+            self.machine.push_input(i64::from(byte));
+        }
+        self.machine.push_input(i64::from(b'\n'));
+    }
+
+    /// Runs until the program wants more input or halts, collecting what it
+    /// printed on the way.
+    pub fn run(&mut self) -> Result<Reply, Error> {
+        let mut reply = Reply::default();
+
+        loop {
+            match self.machine.run()? {
+                State::Output(value) => match decode(value) {
+                    Some(c) => reply.text.push(c),
+                    None => reply.numbers.push(value),
+                },
+                state => {
+                    reply.state = Some(state);
+                    return Ok(reply);
+                }
+            }
+        }
+    }
+
+    /// Sends each line in turn and returns everything printed in response.
+    pub fn run_script<'a>(
+        &mut self,
+        lines: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Reply, Error> {
+        for line in lines {
+            self.send_line(line);
+        }
+
+        self.run()
+    }
+}
+
+fn decode(value: i64) -> Option<char> {
+    u8::try_from(value)
+        .ok()
+        .filter(u8::is_ascii)
+        .map(char::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asm::assemble;
+    use super::*;
+
+    /// Echoes each line upper-cased, then prints 1000 plus the number of
+    /// characters seen once it gets an empty line.
+    fn shout() -> Ascii {
+        Ascii::new(
+            assemble(
+                "start: in [c]
+                        eq [c], #10, [t]
+                        jt [t], #newline
+                        add [n], #1, [n]
+                        lt [c], #97, [t]
+                        jt [t], #print
+                        add [c], #-32, [c]
+                 print: out [c]
+                        jt #1, #start
+                 newline: out #10
+                        jf [n], #done
+                        add [n], [total], [total]
+                        add #0, #0, [n]
+                        jt #1, #start
+                 done:  out [total]
+                        halt
+                 c:     db 0
+                 t:     db 0
+                 n:     db 0
+                 total: db 1000",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn lines() {
+        let mut ascii = shout();
+
+        let reply = ascii.run().unwrap();
+        assert_eq!(Some(State::NeedsInput), reply.state);
+        assert_eq!("", reply.text);
+
+        ascii.send_line("hello\n");
+        let reply = ascii.run().unwrap();
+        assert_eq!("HELLO\n", reply.text);
+        assert!(!reply.is_halted());
+    }
+
+    #[test]
+    fn numeric_results() {
+        let reply = shout().run_script(vec!["abc", "de", ""]).unwrap();
+
+        assert_eq!("ABC\nDE\n\n", reply.text);
+        assert_eq!(vec![1005], reply.numbers);
+        assert!(reply.is_halted());
+    }
+
+    #[test]
+    fn large_numbers_are_not_text() {
+        assert_eq!(Some('\n'), decode(10));
+        assert_eq!(None, decode(128));
+        assert_eq!(None, decode(19_349_939));
+        assert_eq!(None, decode(-1));
+    }
+}
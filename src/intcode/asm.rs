@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+
+use snafu::{ensure, OptionExt, Snafu};
+
+use super::Memory;
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Line {}: unknown mnemonic {:?}", line, mnemonic))]
+    UnknownMnemonic { line: usize, mnemonic: String },
+    #[snafu(display(
+        "Line {}: {} takes {} operands, found {}",
+        line,
+        mnemonic,
+        expected,
+        found
+    ))]
+    OperandCount {
+        line: usize,
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    #[snafu(display("Line {}: can't parse operand {:?}", line, operand))]
+    BadOperand { line: usize, operand: String },
+    #[snafu(display(
+        "Line {}: {} can't write to an immediate",
+        line,
+        mnemonic
+    ))]
+    ImmediateDestination { line: usize, mnemonic: String },
+    #[snafu(display("Line {}: undefined label {:?}", line, label))]
+    UndefinedLabel { line: usize, label: String },
+    #[snafu(display("Line {}: label {:?} is already defined", line, label))]
+    DuplicateLabel { line: usize, label: String },
+    #[snafu(display(
+        "Line {}: expected to be at address {}, but at {}",
+        line,
+        expected,
+        actual
+    ))]
+    AddressMismatch {
+        line: usize,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Mode {
+    Position = 0,
+    Immediate = 1,
+    Relative = 2,
+}
+
+/// A value that may refer to a label, resolved once every label's address
+/// is known.
+#[derive(Debug, PartialEq, Clone)]
+enum Value {
+    Number(i64),
+    Label(String, i64),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct Operand {
+    mode: Mode,
+    value: Value,
+}
+
+#[derive(Debug, PartialEq)]
+enum Statement {
+    Instruction { opcode: i64, operands: Vec<Operand> },
+    Data(Vec<Value>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { operands, .. } => operands.len() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+/// Opcode, operand count and which operand (if any) is written to.
+fn lookup(mnemonic: &str) -> Option<(i64, usize, Option<usize>)> {
+    let spec = match mnemonic {
+        "add" => (1, 3, Some(2)),
+        "mul" => (2, 3, Some(2)),
+        "in" => (3, 1, Some(0)),
+        "out" => (4, 1, None),
+        "jt" => (5, 2, None),
+        "jf" => (6, 2, None),
+        "lt" => (7, 3, Some(2)),
+        "eq" => (8, 3, Some(2)),
+        "arb" => (9, 1, None),
+        "halt" => (99, 0, None),
+        _ => return None,
+    };
+
+    Some(spec)
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(line: usize, text: &str) -> Result<Value, Error> {
+    let text = text.trim();
+    let bad = || BadOperand {
+        line,
+        operand: text,
+    };
+
+    if let Ok(n) = text.parse() {
+        return Ok(Value::Number(n));
+    }
+
+    let (label, offset) = match text.find(['+', '-']) {
+        Some(i) => {
+            let offset: i64 =
+                text[i + 1..].trim().parse().ok().context(bad())?;
+            let sign = if text[i..].starts_with('-') { -1 } else { 1 };
+            (text[..i].trim(), sign * offset)
+        }
+        None => (text, 0),
+    };
+
+    ensure!(is_label(label), bad());
+
+    Ok(Value::Label(label.to_string(), offset))
+}
+
+fn parse_operand(line: usize, text: &str) -> Result<Operand, Error> {
+    let text = text.trim();
+
+    if let Some(rest) = text.strip_prefix('#') {
+        return Ok(Operand {
+            mode: Mode::Immediate,
+            value: parse_value(line, rest)?,
+        });
+    }
+
+    if let Some(inner) =
+        text.strip_prefix('[').and_then(|t| t.strip_suffix(']'))
+    {
+        let inner = inner.trim();
+
+        if let Some(offset) = inner.strip_prefix("rb") {
+            let offset = offset.replace(' ', "");
+            let value = match offset.as_str() {
+                "" => Some(0),
+                o if o.starts_with('+') => o[1..].parse().ok(),
+                o => o.parse().ok(),
+            }
+            .context(BadOperand {
+                line,
+                operand: text,
+            })?;
+
+            return Ok(Operand {
+                mode: Mode::Relative,
+                value: Value::Number(value),
+            });
+        }
+
+        return Ok(Operand {
+            mode: Mode::Position,
+            value: parse_value(line, inner)?,
+        });
+    }
+
+    Ok(Operand {
+        mode: Mode::Immediate,
+        value: parse_value(line, text)?,
+    })
+}
+
+fn parse_statement(line: usize, text: &str) -> Result<Statement, Error> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim()),
+        None => (text, ""),
+    };
+
+    if mnemonic == ".data" || mnemonic == "db" {
+        let values = rest
+            .split(',')
+            .map(|v| parse_value(line, v))
+            .collect::<Result<_, _>>()?;
+
+        return Ok(Statement::Data(values));
+    }
+
+    let (opcode, count, dest) =
+        lookup(mnemonic).context(UnknownMnemonic { line, mnemonic })?;
+
+    let operands = if rest.is_empty() {
+        vec![]
+    } else {
+        rest.split(',')
+            .map(|o| parse_operand(line, o))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    ensure!(
+        operands.len() == count,
+        OperandCount {
+            line,
+            mnemonic,
+            expected: count,
+            found: operands.len(),
+        }
+    );
+
+    if let Some(d) = dest {
+        ensure!(
+            operands[d].mode != Mode::Immediate,
+            ImmediateDestination { line, mnemonic }
+        );
+    }
+
+    Ok(Statement::Instruction { opcode, operands })
+}
+
+/// Splits `label:` prefixes off a line. Numeric prefixes such as the ones
+/// in a disassembly listing assert the current address instead.
+fn split_labels(text: &str) -> (Vec<&str>, &str) {
+    let mut labels = vec![];
+    let mut rest = text.trim();
+
+    while let Some(i) = rest.find(':') {
+        let label = rest[..i].trim();
+
+        if !is_label(label) && label.parse::<usize>().is_err() {
+            break;
+        }
+
+        labels.push(label);
+        rest = rest[i + 1..].trim();
+    }
+
+    (labels, rest)
+}
+
+/// Assembles source text into a memory image.
+///
+/// Each line holds an optional `label:`, then either an instruction such as
+/// `add [9], #3, [rb-1]` or a data directive such as `.data 1, 2, end`.
+/// Operands are `[n]` for position, `#n` for immediate and `[rb+n]` for
+/// relative mode; a bare value is immediate. Anything after `;` is a
+/// comment.
+pub fn assemble(source: &str) -> Result<Memory, Error> {
+    let mut labels = HashMap::new();
+    let mut statements = vec![];
+    let mut address = 0;
+
+    for (n, text) in source.lines().enumerate() {
+        let line = n + 1;
+        let text = text.split(';').next().unwrap();
+        let (names, rest) = split_labels(text);
+
+        for name in names {
+            if let Ok(expected) = name.parse::<usize>() {
+                ensure!(
+                    expected == address,
+                    AddressMismatch {
+                        line,
+                        expected,
+                        actual: address
+                    }
+                );
+            } else {
+                ensure!(
+                    labels.insert(name.to_string(), address).is_none(),
+                    DuplicateLabel { line, label: name }
+                );
+            }
+        }
+
+        if !rest.is_empty() {
+            let statement = parse_statement(line, rest)?;
+            address += statement.size();
+            statements.push((line, statement));
+        }
+    }
+
+    let resolve = |line: usize, value: &Value| -> Result<i64, Error> {
+        match value {
+            Value::Number(n) => Ok(*n),
+            Value::Label(label, offset) => labels
+                .get(label)
+                .map(|a| *a as i64 + offset)
+                .context(UndefinedLabel { line, label }),
+        }
+    };
+
+    let mut words = vec![];
+
+    for (line, statement) in statements {
+        match statement {
+            Statement::Instruction { opcode, operands } => {
+                let modes = operands
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, o| acc * 10 + o.mode as i64);
+                words.push(modes * 100 + opcode);
+
+                for operand in operands {
+                    words.push(resolve(line, &operand.value)?);
+                }
+            }
+            Statement::Data(values) => {
+                for value in values {
+                    words.push(resolve(line, &value)?);
+                }
+            }
+        }
+    }
+
+    Ok(words.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{disasm, execute, format_program, load_program};
+    use super::*;
+
+    fn listing(mem: &Memory) -> String {
+        disasm::disassemble(mem)
+            .iter()
+            .map(|i| format!("{}\n", i))
+            .collect()
+    }
+
+    #[test]
+    fn instructions() {
+        assert_eq!(
+            Ok(vec![1, 9, 10, 3, 1002, 3, 11, 0, 99].into()),
+            assemble(
+                "add [9], [10], [3]
+                 mul [3], #11, [0]
+                 halt"
+            )
+        );
+    }
+
+    #[test]
+    fn relative_operands() {
+        assert_eq!(
+            Ok(vec![109, 1, 204, -1, 21101, 1, 2, 0].into()),
+            assemble("arb #1\nout [rb-1]\nadd #1, #2, [rb+0]")
+        );
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let source = "
+            ; Count down from 3, outputting each step.
+            loop:   out [counter]
+                    add [counter], #-1, [counter]
+                    jt [counter], #loop
+                    halt
+            counter: db 3
+        ";
+
+        let mut mem = assemble(source).unwrap();
+        assert_eq!(vec![4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3], mem);
+
+        let mut outputs = vec![];
+        execute(&mut mem, &mut [].iter(), &mut outputs).unwrap();
+        assert_eq!(vec![3, 2, 1], outputs);
+    }
+
+    #[test]
+    fn label_offsets() {
+        assert_eq!(
+            Ok(vec![1101, 1, 2, 5, 99, 0].into()),
+            assemble("add #1, #2, [end+1]\nend: halt\n.data 0")
+        );
+    }
+
+    #[test]
+    fn round_trips_through_disassembler() {
+        let program = load_program(
+            "3,225,1,225,6,6,1100,1,238,225,104,0,1101,40,71,224,\
+             1001,224,-111,224,4,224,109,-3,21202,3,2,5,99,-7,42",
+        )
+        .unwrap();
+
+        assert_eq!(Ok(program.clone()), assemble(&listing(&program)));
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let mem = assemble("top: in [rb+3]\njf [rb+3], top\nhalt").unwrap();
+
+        assert_eq!(Ok(mem.clone()), load_program(&format_program(&mem)));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(Error::UnknownMnemonic {
+                line: 2,
+                mnemonic: "jmp".to_string()
+            }),
+            assemble("halt\njmp #0")
+        );
+        assert_eq!(
+            Err(Error::OperandCount {
+                line: 1,
+                mnemonic: "add".to_string(),
+                expected: 3,
+                found: 2
+            }),
+            assemble("add #1, #2")
+        );
+        assert_eq!(
+            Err(Error::ImmediateDestination {
+                line: 1,
+                mnemonic: "in".to_string()
+            }),
+            assemble("in #1")
+        );
+        assert_eq!(
+            Err(Error::UndefinedLabel {
+                line: 1,
+                label: "nowhere".to_string()
+            }),
+            assemble("jt #1, nowhere")
+        );
+        assert_eq!(
+            Err(Error::AddressMismatch {
+                line: 2,
+                expected: 2,
+                actual: 1
+            }),
+            assemble("0: halt\n2: halt")
+        );
+    }
+}
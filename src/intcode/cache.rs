@@ -0,0 +1,87 @@
+use super::{Address, Op};
+
+/// Addresses past this aren't cached; programs living out there decode
+/// every time rather than growing the table without bound.
+const MAX_CACHED: Address = 1 << 16;
+
+/// Decoded instructions keyed by the address they start at.
+///
+/// Any write that lands inside a cached instruction evicts it, so
+/// self-modifying programs see their changes.
+#[derive(Debug, Clone, Default)]
+pub(super) struct DecodeCache {
+    ops: Vec<Option<Op>>,
+}
+
+impl DecodeCache {
+    pub(super) fn get(&self, address: Address) -> Option<Op> {
+        self.ops.get(address).copied().flatten()
+    }
+
+    pub(super) fn insert(&mut self, address: Address, op: Op) {
+        if address >= MAX_CACHED {
+            return;
+        }
+
+        if self.ops.len() <= address {
+            self.ops.resize(address + 1, None);
+        }
+
+        self.ops[address] = Some(op);
+    }
+
+    /// Evicts whichever cached instruction covers `address`, if any.
+    pub(super) fn invalidate(&mut self, address: Address) {
+        for start in address.saturating_sub(3)..=address {
+            if let Some(slot) = self.ops.get_mut(start) {
+                if slot.map_or(false, |op| start + op.size() > address) {
+                    *slot = None;
+                }
+            }
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.ops.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Parameter;
+    use super::*;
+
+    fn add() -> Op {
+        Op::Add {
+            s1: Parameter::Immediate(1),
+            s2: Parameter::Immediate(2),
+            dest: Parameter::Position(0),
+        }
+    }
+
+    #[test]
+    fn invalidates_covering_instruction() {
+        let mut cache = DecodeCache::default();
+        cache.insert(4, add());
+        cache.insert(8, Op::Halt);
+
+        cache.invalidate(9);
+        assert_eq!(Some(add()), cache.get(4));
+        assert_eq!(Some(Op::Halt), cache.get(8));
+
+        cache.invalidate(7);
+        assert_eq!(None, cache.get(4));
+        assert_eq!(Some(Op::Halt), cache.get(8));
+
+        cache.invalidate(8);
+        assert_eq!(None, cache.get(8));
+    }
+
+    #[test]
+    fn ignores_far_addresses() {
+        let mut cache = DecodeCache::default();
+        cache.insert(MAX_CACHED, Op::Halt);
+
+        assert_eq!(None, cache.get(MAX_CACHED));
+    }
+}
@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use super::{
+    drive, next_op, Address, Error, Machine, Memory, Op, Parameter, Sink,
+};
+
+/// Where an IR instruction reads a value from.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Value {
+    Const(i64),
+    Cell(Address),
+    Rel(i64),
+}
+
+/// Where an IR instruction writes a value to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Place {
+    Cell(Address),
+    Rel(i64),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum BinOp {
+    Add,
+    Mul,
+    LessThan,
+    Equals,
+}
+
+impl BinOp {
+    fn apply(self, a: i64, b: i64) -> i64 {
+        match self {
+            BinOp::Add => a + b,
+            BinOp::Mul => a * b,
+            BinOp::LessThan => (a < b) as i64,
+            BinOp::Equals => (a == b) as i64,
+        }
+    }
+
+    /// Like `apply`, but `None` where that would overflow.
+    fn fold(self, a: i64, b: i64) -> Option<i64> {
+        match self {
+            BinOp::Add => a.checked_add(b),
+            BinOp::Mul => a.checked_mul(b),
+            _ => Some(self.apply(a, b)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Inst {
+    Binary {
+        op: BinOp,
+        a: Value,
+        b: Value,
+        dest: Place,
+    },
+    /// A binary op whose operands were both immediate. Left as `Binary`
+    /// if the result would overflow, as the op may never run.
+    Set {
+        value: i64,
+        dest: Place,
+    },
+    Input {
+        dest: Place,
+    },
+    Output {
+        src: Value,
+    },
+    AdjustBase {
+        by: Value,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Target {
+    Block(usize),
+    /// Only known at run time, or not the start of a compiled block.
+    Dynamic(Value),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Exit {
+    Goto(usize),
+    Jump(Target),
+    Branch {
+        cond: Value,
+        if_true: bool,
+        target: Target,
+        otherwise: usize,
+    },
+    Halt,
+    /// Something the compiler couldn't decode. The interpreter takes over
+    /// from here and reports any error itself.
+    Interpret,
+}
+
+#[derive(Debug)]
+struct Block {
+    insts: Vec<(Address, Inst)>,
+    exit: (Address, Exit),
+}
+
+fn value(p: Parameter) -> Value {
+    match p {
+        Parameter::Position(address) => Value::Cell(address),
+        Parameter::Immediate(n) => Value::Const(n),
+        Parameter::Relative(offset) => Value::Rel(offset),
+    }
+}
+
+fn place(p: Parameter) -> Place {
+    match p {
+        Parameter::Position(address) => Place::Cell(address),
+        Parameter::Relative(offset) => Place::Rel(offset),
+        Parameter::Immediate(_) => unreachable!("decoder rejects these"),
+    }
+}
+
+fn lower(op: Op) -> Inst {
+    use Op::*;
+
+    let binary = |op, s1, s2, dest| {
+        let (a, b) = (value(s1), value(s2));
+        let folded = match (a, b) {
+            (Value::Const(a), Value::Const(b)) => BinOp::fold(op, a, b),
+            _ => None,
+        };
+
+        match folded {
+            Some(value) => Inst::Set {
+                value,
+                dest: place(dest),
+            },
+            None => Inst::Binary {
+                op,
+                a,
+                b,
+                dest: place(dest),
+            },
+        }
+    };
+
+    match op {
+        Add { s1, s2, dest } => binary(BinOp::Add, s1, s2, dest),
+        Mul { s1, s2, dest } => binary(BinOp::Mul, s1, s2, dest),
+        LessThan { s1, s2, dest } => binary(BinOp::LessThan, s1, s2, dest),
+        Equals { s1, s2, dest } => binary(BinOp::Equals, s1, s2, dest),
+        Input { dest } => Inst::Input { dest: place(dest) },
+        Output { src } => Inst::Output { src: value(src) },
+        AdjustRelativeBase { offset } => Inst::AdjustBase { by: value(offset) },
+        _ => unreachable!("{:?} ends a block", op),
+    }
+}
+
+/// A memory image split into basic blocks and lowered to a small IR.
+///
+/// Blocks are discovered from address 0 by following every jump whose
+/// target is an immediate. Jumps to anywhere else, writes into the code
+/// and anything the compiler doesn't understand hand the rest of the run
+/// over to the interpreter, so the results always match `execute`.
+#[derive(Debug)]
+pub struct Program {
+    blocks: Vec<Block>,
+    starts: HashMap<Address, usize>,
+    code: Vec<(Address, i64)>,
+    is_code: Vec<bool>,
+}
+
+impl Program {
+    pub fn compile(mem: &Memory) -> Program {
+        let mut program = Program {
+            blocks: vec![],
+            starts: HashMap::new(),
+            code: vec![],
+            is_code: vec![],
+        };
+
+        let mut pending = vec![0];
+        program.block_for(0, &mut pending);
+
+        while let Some(start) = pending.pop() {
+            let block = program.compile_block(mem, start, &mut pending);
+            let index = program.starts[&start];
+            program.blocks[index] = block;
+        }
+
+        program.code.sort_unstable();
+        program.code.dedup();
+
+        program
+    }
+
+    fn block_for(
+        &mut self,
+        start: Address,
+        pending: &mut Vec<Address>,
+    ) -> usize {
+        let blocks = &mut self.blocks;
+
+        *self.starts.entry(start).or_insert_with(|| {
+            pending.push(start);
+            blocks.push(Block {
+                insts: vec![],
+                exit: (start, Exit::Interpret),
+            });
+            blocks.len() - 1
+        })
+    }
+
+    fn target(
+        &mut self,
+        dest: Parameter,
+        pending: &mut Vec<Address>,
+    ) -> Target {
+        match value(dest) {
+            Value::Const(t) if t >= 0 => {
+                Target::Block(self.block_for(t as Address, pending))
+            }
+            v => Target::Dynamic(v),
+        }
+    }
+
+    fn compile_block(
+        &mut self,
+        mem: &Memory,
+        start: Address,
+        pending: &mut Vec<Address>,
+    ) -> Block {
+        let mut insts = vec![];
+        let mut ip = start;
+
+        let exit = loop {
+            let words = [mem[ip], mem[ip + 1], mem[ip + 2], mem[ip + 3]];
+            let op = match next_op(&words) {
+                Ok(op) => op,
+                Err(_) => break Exit::Interpret,
+            };
+
+            for address in ip..ip + op.size() {
+                self.code.push((address, mem[address]));
+                if self.is_code.len() <= address {
+                    self.is_code.resize(address + 1, false);
+                }
+                self.is_code[address] = true;
+            }
+
+            let (cmp, dest, if_true) = match op {
+                Op::Halt => break Exit::Halt,
+                Op::JumpIfTrue { cmp, dest } => (cmp, dest, true),
+                Op::JumpIfFalse { cmp, dest } => (cmp, dest, false),
+                _ => {
+                    insts.push((ip, lower(op)));
+                    ip += op.size();
+                    continue;
+                }
+            };
+
+            let next = ip + op.size();
+
+            break match value(cmp) {
+                Value::Const(c) if (c != 0) == if_true => {
+                    Exit::Jump(self.target(dest, pending))
+                }
+                Value::Const(_) => Exit::Goto(self.block_for(next, pending)),
+                cond => Exit::Branch {
+                    cond,
+                    if_true,
+                    target: self.target(dest, pending),
+                    otherwise: self.block_for(next, pending),
+                },
+            };
+        };
+
+        Block {
+            insts,
+            exit: (ip, exit),
+        }
+    }
+
+    fn is_code(&self, address: Address) -> bool {
+        self.is_code.get(address).copied().unwrap_or(false)
+    }
+
+    /// Whether `mem` has the same code this program was compiled from.
+    pub fn matches(&self, mem: &Memory) -> bool {
+        self.code
+            .iter()
+            .all(|(address, word)| mem[*address] == *word)
+    }
+
+    /// Runs the compiled blocks against `mem`, which starts out as a copy
+    /// of the compiled image with any data changes the caller likes.
+    pub fn run<'a>(
+        &self,
+        mem: &mut Memory,
+        inputs: &mut impl Iterator<Item = &'a i64>,
+        mut output: impl Sink,
+    ) -> Result<(), Error> {
+        if !self.matches(mem) {
+            return interpret(mem, 0, 0, inputs, output);
+        }
+
+        let mut relative_base = 0;
+        let mut block = self.starts[&0];
+
+        let read = |v: Value, mem: &Memory, base: i64| match v {
+            Value::Const(n) => Some(n),
+            Value::Cell(address) => Some(mem[address]),
+            Value::Rel(offset) => {
+                Address::try_from(base + offset).ok().map(|a| mem[a])
+            }
+        };
+
+        let write_address = |p: Place, base: i64| {
+            match p {
+                Place::Cell(address) => Some(address),
+                Place::Rel(offset) => Address::try_from(base + offset).ok(),
+            }
+            .filter(|a| !self.is_code(*a))
+        };
+
+        loop {
+            let Block { insts, exit } = &self.blocks[block];
+
+            for (ip, inst) in insts {
+                let ip = *ip;
+                let base = relative_base;
+                let bail = |mem: &mut Memory, inputs, output| {
+                    interpret(mem, ip, base, inputs, output)
+                };
+
+                match *inst {
+                    Inst::Binary { op, a, b, dest } => {
+                        match (
+                            read(a, mem, base),
+                            read(b, mem, base),
+                            write_address(dest, base),
+                        ) {
+                            (Some(a), Some(b), Some(address)) => {
+                                mem[address] = op.apply(a, b)
+                            }
+                            _ => return bail(mem, inputs, output),
+                        }
+                    }
+                    Inst::Set { value, dest } => {
+                        match write_address(dest, base) {
+                            Some(address) => mem[address] = value,
+                            None => return bail(mem, inputs, output),
+                        }
+                    }
+                    Inst::Input { dest } => match write_address(dest, base) {
+                        Some(address) => match inputs.next() {
+                            Some(value) => mem[address] = *value,
+                            None => return bail(mem, inputs, output),
+                        },
+                        None => return bail(mem, inputs, output),
+                    },
+                    Inst::Output { src } => match read(src, mem, base) {
+                        Some(value) => output.emit(value),
+                        None => return bail(mem, inputs, output),
+                    },
+                    Inst::AdjustBase { by } => match read(by, mem, base) {
+                        Some(value) => relative_base += value,
+                        None => return bail(mem, inputs, output),
+                    },
+                }
+            }
+
+            let (ip, exit) = *exit;
+            let base = relative_base;
+
+            let target = match exit {
+                Exit::Halt => return Ok(()),
+                Exit::Interpret => {
+                    return interpret(mem, ip, base, inputs, output)
+                }
+                Exit::Goto(next) => {
+                    block = next;
+                    continue;
+                }
+                Exit::Jump(target) => target,
+                Exit::Branch {
+                    cond,
+                    if_true,
+                    target,
+                    otherwise,
+                } => match read(cond, mem, base) {
+                    Some(c) if (c != 0) == if_true => target,
+                    Some(_) => {
+                        block = otherwise;
+                        continue;
+                    }
+                    None => return interpret(mem, ip, base, inputs, output),
+                },
+            };
+
+            block = match target {
+                Target::Block(next) => next,
+                Target::Dynamic(v) => match read(v, mem, base)
+                    .and_then(|t| Address::try_from(t).ok())
+                {
+                    Some(address) => match self.starts.get(&address) {
+                        Some(next) => *next,
+                        None => {
+                            return interpret(
+                                mem, address, base, inputs, output,
+                            )
+                        }
+                    },
+                    None => return interpret(mem, ip, base, inputs, output),
+                },
+            };
+        }
+    }
+}
+
+/// Finishes a run in the plain interpreter, starting at `ip`.
+fn interpret<'a>(
+    mem: &mut Memory,
+    ip: Address,
+    relative_base: i64,
+    inputs: &mut impl Iterator<Item = &'a i64>,
+    output: impl Sink,
+) -> Result<(), Error> {
+    let mut machine = Machine::new(std::mem::take(mem));
+    machine.ip = ip;
+    machine.relative_base = relative_base;
+
+    let result = drive(&mut machine, inputs, output, &mut ());
+    *mem = machine.into_memory();
+
+    result
+}
+
+/// Compiles and runs `mem` in one go. Behaves exactly like
+/// `intcode::execute`.
+pub fn execute<'a>(
+    mem: &mut Memory,
+    inputs: &mut impl Iterator<Item = &'a i64>,
+    output: impl Sink,
+) -> Result<(), Error> {
+    Program::compile(mem).run(mem, inputs, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{asm::assemble, load_program};
+    use super::*;
+    use std::fs;
+
+    /// Runs `mem` through both the interpreter and the compiler and
+    /// checks they agree on outputs, final memory and errors.
+    fn differential(mem: &Memory, inputs: &[i64]) {
+        let mut interpreted = mem.clone();
+        let mut compiled = mem.clone();
+        let mut expected = vec![];
+        let mut actual = vec![];
+
+        let expected_result = super::super::execute(
+            &mut interpreted,
+            &mut inputs.iter(),
+            &mut expected,
+        );
+        let actual_result =
+            execute(&mut compiled, &mut inputs.iter(), &mut actual);
+
+        assert_eq!(expected_result, actual_result);
+        assert_eq!(expected, actual);
+        assert_eq!(interpreted, compiled);
+    }
+
+    fn input(path: &str) -> Memory {
+        load_program(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn folds_immediate_arithmetic() {
+        let program = Program::compile(
+            &assemble(
+                "add #2, #3, [10]
+                 jt #1, #end
+                 .data 0
+                 end: halt",
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(
+            vec![(
+                0,
+                Inst::Set {
+                    value: 5,
+                    dest: Place::Cell(10)
+                }
+            )],
+            program.blocks[0].insts
+        );
+        assert_eq!((4, Exit::Jump(Target::Block(1))), program.blocks[0].exit);
+        assert_eq!((8, Exit::Halt), program.blocks[1].exit);
+        assert!(!program.is_code(7));
+    }
+
+    #[test]
+    fn splits_at_branches() {
+        let program = Program::compile(
+            &assemble(
+                "loop: add [counter], #-1, [counter]
+                       jt [counter], #loop
+                       halt
+                 counter: db 3",
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(2, program.blocks.len());
+        assert_eq!(
+            Exit::Branch {
+                cond: Value::Cell(8),
+                if_true: true,
+                target: Target::Block(0),
+                otherwise: 1
+            },
+            program.blocks[0].exit.1
+        );
+        assert!(!program.is_code(8));
+    }
+
+    #[test]
+    fn same_as_interpreter_day5() {
+        let program = input("inputs/5.txt");
+
+        for id in &[1, 5, 8] {
+            differential(&program, &[*id]);
+        }
+    }
+
+    #[test]
+    fn same_as_interpreter_day2() {
+        let program = input("inputs/2.txt");
+
+        for noun in (0..100).step_by(7) {
+            for verb in (0..100).step_by(11) {
+                let mut mem = program.clone();
+                mem[1] = noun;
+                mem[2] = verb;
+                differential(&mem, &[]);
+            }
+        }
+    }
+
+    #[test]
+    fn same_as_interpreter_relative() {
+        let quine = load_program(
+            "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99",
+        )
+        .unwrap();
+        differential(&quine, &[]);
+
+        differential(&load_program("104,1125899906842624,99").unwrap(), &[]);
+    }
+
+    #[test]
+    fn same_as_interpreter_dynamic_jumps() {
+        // Calls a subroutine twice, returning through the stack.
+        let program = assemble(
+            "       arb #stack
+                    add #ret1, #0, [rb+0]
+                    jt #1, #double
+             ret1:  add #ret2, #0, [rb+0]
+                    jt #1, #double
+             ret2:  out [value]
+                    halt
+             double: mul [value], #2, [value]
+                    jt #1, [rb+0]
+             value: db 3
+             stack: db 0",
+        )
+        .unwrap();
+
+        differential(&program, &[]);
+    }
+
+    #[test]
+    fn same_as_interpreter_self_modifying() {
+        let program = assemble(
+            "start: out #1
+                    eq [flag], #1, [t]
+                    jt [t], #end
+                    add #1, #0, [flag]
+                    add #2, #0, [start+1]
+                    jt #1, #start
+             end:   halt
+             flag:  db 0
+             t:     db 0",
+        )
+        .unwrap();
+
+        differential(&program, &[]);
+        differential(&load_program("1,1,1,4,99,5,6,0,99").unwrap(), &[]);
+    }
+
+    #[test]
+    fn same_as_interpreter_on_errors() {
+        let feedback = load_program(
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,\
+             27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5",
+        )
+        .unwrap();
+
+        differential(&feedback, &[9, 0]);
+        differential(&load_program("1101,1,1,0,42").unwrap(), &[]);
+        differential(&load_program("109,1,204,-2,99").unwrap(), &[]);
+        differential(&load_program("1105,1,-3").unwrap(), &[]);
+    }
+
+    #[test]
+    fn same_as_interpreter_with_overflowing_constants() {
+        // The multiply is always skipped, but the compiler can't tell.
+        let program = assemble(
+            "      jt [flag], #end
+                   mul #4611686018427387904, #4, [0]
+             end:  out #1
+                   halt
+             flag: db 1",
+        )
+        .unwrap();
+
+        differential(&program, &[]);
+    }
+
+    #[test]
+    fn interprets_patched_code() {
+        let mut mem = load_program("1,0,0,0,99").unwrap();
+        let program = Program::compile(&mem);
+        mem[1] = 4;
+
+        assert!(!program.matches(&mem));
+        program.run(&mut mem, &mut [].iter(), &mut vec![]).unwrap();
+        assert_eq!(100, mem[0]);
+    }
+}
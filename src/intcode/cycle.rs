@@ -0,0 +1,105 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use super::{Address, Memory};
+
+/// Spots a machine that has come back to a state it was already in.
+///
+/// Every `interval` steps the ip, relative base and memory are hashed and
+/// compared with one saved hash, which is replaced after 1, 2, 4, ... more
+/// samples (Brent's algorithm). Seeing the saved hash again without any
+/// input in between means the program will go round the same loop forever.
+/// This finds any loop eventually while only ever keeping one hash, at the
+/// cost of a vanishingly small chance of a false positive.
+#[derive(Debug, Clone)]
+pub(super) struct CycleDetector {
+    interval: usize,
+    /// The step at which to take the next sample.
+    next: usize,
+    saved: Option<u64>,
+    /// Samples to take before replacing `saved`, and how many have been.
+    power: usize,
+    taken: usize,
+}
+
+impl CycleDetector {
+    pub(super) fn new(interval: usize) -> CycleDetector {
+        CycleDetector {
+            interval: interval.max(1),
+            next: 0,
+            saved: None,
+            power: 1,
+            taken: 0,
+        }
+    }
+
+    /// Returns true if the state at `steps` matches the saved one.
+    pub(super) fn check(
+        &mut self,
+        steps: usize,
+        ip: Address,
+        relative_base: i64,
+        mem: &Memory,
+    ) -> bool {
+        if steps < self.next {
+            return false;
+        }
+        self.next = steps + self.interval;
+
+        let mut hasher = DefaultHasher::new();
+        (ip, relative_base, mem).hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self.saved == Some(hash) {
+            return true;
+        }
+
+        self.taken += 1;
+        if self.taken == self.power {
+            self.saved = Some(hash);
+            self.power *= 2;
+            self.taken = 0;
+        }
+
+        false
+    }
+
+    /// Forgets earlier states, since a new input can send the program
+    /// somewhere else.
+    pub(super) fn reset(&mut self) {
+        self.saved = None;
+        self.power = 1;
+        self.taken = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_state() {
+        let mem = Memory::from(vec![1, 2, 3]);
+        let mut detector = CycleDetector::new(2);
+
+        assert!(!detector.check(0, 0, 0, &mem));
+        assert!(!detector.check(1, 0, 0, &mem));
+        assert!(!detector.check(2, 4, 0, &mem));
+        assert!(detector.check(4, 0, 0, &mem));
+
+        detector.reset();
+        assert!(!detector.check(6, 0, 0, &mem));
+    }
+
+    #[test]
+    fn long_loops_are_found() {
+        let mut detector = CycleDetector::new(1);
+        let mem = Memory::new();
+
+        // A loop through 100 different states, gone round a few times.
+        let found =
+            (0..1000).find(|&step| detector.check(step, step % 100, 0, &mem));
+
+        assert!(found.is_some());
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use super::disasm::{self, Instruction};
+use super::{Address, Error, Machine, Memory, State};
+
+/// Why the debugger handed control back.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Stop {
+    /// One instruction ran and nothing interesting happened.
+    Stepped,
+    State(State),
+    Breakpoint(Address),
+    Watchpoint {
+        address: Address,
+        old: i64,
+        new: i64,
+    },
+}
+
+/// Wraps a `Machine` with breakpoints on addresses and watchpoints on
+/// memory cells.
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: BTreeSet<Address>,
+    watchpoints: BTreeMap<Address, i64>,
+}
+
+impl Debugger {
+    pub fn new(mem: Memory) -> Debugger {
+        Debugger {
+            machine: Machine::new(mem),
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.machine.push_input(value);
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &Address> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_breakpoint(&mut self, address: Address) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: Address) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn watchpoints(&self) -> impl Iterator<Item = &Address> {
+        self.watchpoints.keys()
+    }
+
+    pub fn watch(&mut self, address: Address) {
+        let value = self.machine.memory()[address];
+        self.watchpoints.insert(address, value);
+    }
+
+    pub fn unwatch(&mut self, address: Address) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    pub fn peek(&self, range: Range<Address>) -> Vec<i64> {
+        range.map(|a| self.machine.memory()[a]).collect()
+    }
+
+    /// Overwrites a cell. Watchpoints on it take the new value silently.
+    pub fn poke(&mut self, address: Address, value: i64) {
+        self.machine.memory_mut()[address] = value;
+
+        if let Some(watched) = self.watchpoints.get_mut(&address) {
+            *watched = value;
+        }
+    }
+
+    pub fn next_instruction(&self) -> Instruction {
+        disasm::decode_at(self.machine.memory(), self.machine.ip())
+    }
+
+    pub fn step(&mut self) -> Result<Stop, Error> {
+        if let Some(state) = self.machine.step()? {
+            return Ok(Stop::State(state));
+        }
+
+        let mem = self.machine.memory();
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = mem[*address];
+
+            if new != *old {
+                let stop = Stop::Watchpoint {
+                    address: *address,
+                    old: *old,
+                    new,
+                };
+                *old = new;
+                return Ok(stop);
+            }
+        }
+
+        Ok(Stop::Stepped)
+    }
+
+    /// Steps until something other than `Stop::Stepped` happens or a
+    /// breakpoint is reached. Always runs at least one instruction, so
+    /// resuming from a breakpoint makes progress.
+    pub fn resume(&mut self) -> Result<Stop, Error> {
+        loop {
+            match self.step()? {
+                Stop::Stepped => {
+                    let ip = self.machine.ip();
+                    if self.breakpoints.contains(&ip) {
+                        return Ok(Stop::Breakpoint(ip));
+                    }
+                }
+                stop => return Ok(stop),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asm::assemble;
+    use super::*;
+
+    fn countdown() -> Debugger {
+        Debugger::new(
+            assemble(
+                "loop: out [counter]
+                       add [counter], #-1, [counter]
+                       jt [counter], #loop
+                       halt
+                 counter: db 2",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn stepping() {
+        let mut debugger = countdown();
+
+        assert_eq!("out [10]", debugger.next_instruction().text);
+        assert_eq!(Ok(Stop::State(State::Output(2))), debugger.step());
+        assert_eq!("add [10], #-1, [10]", debugger.next_instruction().text);
+        assert_eq!(Ok(Stop::Stepped), debugger.step());
+        assert_eq!(6, debugger.machine().ip());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = countdown();
+        debugger.add_breakpoint(6);
+
+        assert_eq!(Ok(Stop::State(State::Output(2))), debugger.resume());
+        assert_eq!(Ok(Stop::Breakpoint(6)), debugger.resume());
+        assert_eq!(Ok(Stop::State(State::Output(1))), debugger.resume());
+        assert_eq!(Ok(Stop::Breakpoint(6)), debugger.resume());
+
+        assert!(debugger.remove_breakpoint(6));
+        assert_eq!(Ok(Stop::State(State::Halted)), debugger.resume());
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut debugger = countdown();
+        debugger.watch(10);
+
+        assert_eq!(Ok(Stop::State(State::Output(2))), debugger.resume());
+        assert_eq!(
+            Ok(Stop::Watchpoint {
+                address: 10,
+                old: 2,
+                new: 1
+            }),
+            debugger.resume()
+        );
+        assert_eq!(6, debugger.machine().ip());
+    }
+
+    #[test]
+    fn poking() {
+        let mut debugger = countdown();
+        debugger.watch(10);
+        debugger.poke(10, 1);
+
+        assert_eq!(vec![99, 1], debugger.peek(9..11));
+        assert_eq!(Ok(Stop::State(State::Output(1))), debugger.resume());
+        assert!(matches!(
+            debugger.resume(),
+            Ok(Stop::Watchpoint { new: 0, .. })
+        ));
+        assert_eq!(Ok(Stop::State(State::Halted)), debugger.resume());
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+use super::{next_op, Address, Memory, Op, Parameter};
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Parameter::Position(address) => write!(f, "[{}]", address),
+            Parameter::Immediate(value) => write!(f, "#{}", value),
+            Parameter::Relative(offset) if *offset < 0 => {
+                write!(f, "[rb-{}]", -offset)
+            }
+            Parameter::Relative(offset) => write!(f, "[rb+{}]", offset),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Op::*;
+
+        match self {
+            Halt => write!(f, "halt"),
+            Add { s1, s2, dest } => write!(f, "add {}, {}, {}", s1, s2, dest),
+            Mul { s1, s2, dest } => write!(f, "mul {}, {}, {}", s1, s2, dest),
+            Input { dest } => write!(f, "in {}", dest),
+            Output { src } => write!(f, "out {}", src),
+            JumpIfTrue { cmp, dest } => write!(f, "jt {}, {}", cmp, dest),
+            JumpIfFalse { cmp, dest } => write!(f, "jf {}, {}", cmp, dest),
+            LessThan { s1, s2, dest } => {
+                write!(f, "lt {}, {}, {}", s1, s2, dest)
+            }
+            Equals { s1, s2, dest } => write!(f, "eq {}, {}, {}", s1, s2, dest),
+            AdjustRelativeBase { offset } => write!(f, "arb {}", offset),
+        }
+    }
+}
+
+/// One line of a listing: a decoded instruction, or a single data word
+/// that doesn't decode.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    pub address: Address,
+    pub size: usize,
+    pub text: String,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>5}: {}", self.address, self.text)
+    }
+}
+
+/// Decodes the instruction at `address`. Instructions whose operands would
+/// run past the end of `mem` are shown as data, so the listing assembles
+/// back to the same image.
+pub fn decode_at(mem: &Memory, address: Address) -> Instruction {
+    let words = [
+        mem[address],
+        mem[address + 1],
+        mem[address + 2],
+        mem[address + 3],
+    ];
+
+    match next_op(&words) {
+        Ok(op) if address + op.size() <= mem.len() => Instruction {
+            address,
+            size: op.size(),
+            text: op.to_string(),
+        },
+        _ => Instruction {
+            address,
+            size: 1,
+            text: format!(".data {}", words[0]),
+        },
+    }
+}
+
+/// Decodes the whole image front to back.
+pub fn disassemble(mem: &Memory) -> Vec<Instruction> {
+    let mut listing = vec![];
+    let mut address = 0;
+
+    while address < mem.len() {
+        let instruction = decode_at(mem, address);
+        address += instruction.size;
+        listing.push(instruction);
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::asm::assemble;
+    use super::*;
+
+    fn lines(program: Vec<i64>) -> Vec<String> {
+        disassemble(&program.into())
+            .iter()
+            .map(|i| i.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn listing() {
+        assert_eq!(
+            vec![
+                "    0: add [9], [10], [3]",
+                "    4: mul [3], #11, [0]",
+                "    8: halt",
+                "    9: .data 30",
+                "   10: .data 40",
+                "   11: .data 50",
+            ],
+            lines(vec![1, 9, 10, 3, 1002, 3, 11, 0, 99, 30, 40, 50])
+        );
+    }
+
+    #[test]
+    fn relative_operands() {
+        assert_eq!(
+            vec!["    0: arb #1", "    2: out [rb-1]", "    4: jt [rb+3], #0",],
+            lines(vec![109, 1, 204, -1, 1205, 3, 0])
+        );
+    }
+
+    #[test]
+    fn undecodable_words() {
+        assert_eq!(
+            vec!["    0: .data -5", "    1: .data 42", "    2: in [0]"],
+            lines(vec![-5, 42, 3, 0])
+        );
+    }
+
+    #[test]
+    fn truncated_instruction() {
+        let program: Memory = vec![99, 4].into();
+        let listing: Vec<String> = disassemble(&program)
+            .iter()
+            .map(|i| i.text.clone())
+            .collect();
+
+        assert_eq!(vec!["halt", ".data 4"], listing);
+        assert_eq!(Ok(program), assemble(&listing.join("\n")));
+    }
+}
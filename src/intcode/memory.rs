@@ -0,0 +1,186 @@
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::ops::{Index, IndexMut};
+
+use super::Address;
+
+const PAGE_SIZE: usize = 1024;
+
+type Page = Box<[i64; PAGE_SIZE]>;
+
+static ZERO_PAGE: [i64; PAGE_SIZE] = [0; PAGE_SIZE];
+
+/// Sparse, auto-growing intcode memory.
+///
+/// Cells are stored in fixed size pages that are only allocated once
+/// something is written to them. Reading an address that was never written
+/// yields 0, so a write to a far away address doesn't allocate everything
+/// in between.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    pages: BTreeMap<usize, Page>,
+    len: usize,
+}
+
+impl Memory {
+    pub fn new() -> Memory {
+        Memory::default()
+    }
+
+    /// One past the highest address that has been loaded or written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, address: Address) -> i64 {
+        self[address]
+    }
+
+    pub fn set(&mut self, address: Address, value: i64) {
+        self[address] = value;
+    }
+
+    fn page(&self, n: usize) -> &[i64; PAGE_SIZE] {
+        self.pages.get(&n).map_or(&ZERO_PAGE, |p| p)
+    }
+
+    /// Copies `0..len()` out into a dense vector.
+    pub fn to_vec(&self) -> Vec<i64> {
+        (0..self.len).map(|a| self[a]).collect()
+    }
+}
+
+impl Index<Address> for Memory {
+    type Output = i64;
+
+    fn index(&self, address: Address) -> &i64 {
+        &self.page(address / PAGE_SIZE)[address % PAGE_SIZE]
+    }
+}
+
+impl IndexMut<Address> for Memory {
+    fn index_mut(&mut self, address: Address) -> &mut i64 {
+        self.len = self.len.max(address + 1);
+
+        let page = self
+            .pages
+            .entry(address / PAGE_SIZE)
+            .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+
+        &mut page[address % PAGE_SIZE]
+    }
+}
+
+impl PartialEq for Memory {
+    fn eq(&self, other: &Memory) -> bool {
+        self.len == other.len
+            && self
+                .pages
+                .keys()
+                .chain(other.pages.keys())
+                .all(|n| self.page(*n)[..] == other.page(*n)[..])
+    }
+}
+
+impl Eq for Memory {}
+
+impl Hash for Memory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len.hash(state);
+
+        // Skip all-zero pages so that equal memories hash the same
+        // whether or not those pages were ever allocated.
+        for (n, page) in &self.pages {
+            if page[..] != ZERO_PAGE[..] {
+                n.hash(state);
+                page[..].hash(state);
+            }
+        }
+    }
+}
+
+impl PartialEq<Vec<i64>> for Memory {
+    fn eq(&self, other: &Vec<i64>) -> bool {
+        self.len == other.len()
+            && other.iter().enumerate().all(|(a, v)| self[a] == *v)
+    }
+}
+
+impl PartialEq<Memory> for Vec<i64> {
+    fn eq(&self, other: &Memory) -> bool {
+        other == self
+    }
+}
+
+impl FromIterator<i64> for Memory {
+    fn from_iter<I: IntoIterator<Item = i64>>(iter: I) -> Memory {
+        let mut mem = Memory::new();
+
+        for (address, value) in iter.into_iter().enumerate() {
+            mem[address] = value;
+        }
+
+        mem
+    }
+}
+
+impl From<Vec<i64>> for Memory {
+    fn from(values: Vec<i64>) -> Memory {
+        values.into_iter().collect()
+    }
+}
+
+impl From<&[i64]> for Memory {
+    fn from(values: &[i64]) -> Memory {
+        values.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reading_past_the_end() {
+        let mem = Memory::from(vec![1, 2, 3]);
+
+        assert_eq!(0, mem[3]);
+        assert_eq!(0, mem[5000]);
+        assert_eq!(3, mem.len());
+    }
+
+    #[test]
+    fn writing_past_the_end() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+
+        mem[5] = 6;
+
+        assert_eq!(vec![1, 2, 3, 0, 0, 6], mem);
+    }
+
+    #[test]
+    fn writing_far_away_is_sparse() {
+        let mut mem = Memory::from(vec![1, 2, 3]);
+
+        mem[1_000_000_000] = 42;
+
+        assert_eq!(2, mem.pages.len());
+        assert_eq!(1_000_000_001, mem.len());
+        assert_eq!(42, mem[1_000_000_000]);
+        assert_eq!(0, mem[999_999_999]);
+    }
+
+    #[test]
+    fn equality_ignores_allocated_zeros() {
+        let mut sparse = Memory::new();
+        sparse[2000] = 0;
+
+        assert_eq!(Memory::from(vec![0; 2001]), sparse);
+        assert_ne!(Memory::from(vec![0; 2000]), sparse);
+    }
+}
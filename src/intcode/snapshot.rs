@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead};
+
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use super::{format_program, Address, Memory};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read snapshot: {}", source))]
+    Read { source: io::Error },
+    #[snafu(display("Line {}: {}", line, message))]
+    Syntax { line: usize, message: String },
+    #[snafu(display("Missing or invalid field {:?}", field))]
+    Field { field: String },
+}
+
+/// Everything needed to carry on running a machine later, possibly in
+/// another process.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub ip: Address,
+    pub relative_base: i64,
+    pub steps: usize,
+    pub halted: bool,
+    pub memory: Memory,
+    /// Inputs queued but not read yet. Outputs are handed over as soon as
+    /// they're produced, so there are never any pending.
+    pub inputs: Vec<i64>,
+}
+
+const FIELDS: [&str; 6] =
+    ["ip", "relative_base", "steps", "halted", "inputs", "memory"];
+
+fn format_list(values: &[i64]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    values.join(",")
+}
+
+fn parse_list(text: &str) -> Option<Vec<i64>> {
+    if text.is_empty() {
+        return Some(vec![]);
+    }
+
+    text.split(',').map(|v| v.trim().parse().ok()).collect()
+}
+
+impl Snapshot {
+    /// Writes one `name value` line per field, with lists comma separated.
+    pub fn write(&self, mut writer: impl io::Write) -> io::Result<()> {
+        writeln!(writer, "ip {}", self.ip)?;
+        writeln!(writer, "relative_base {}", self.relative_base)?;
+        writeln!(writer, "steps {}", self.steps)?;
+        writeln!(writer, "halted {}", self.halted)?;
+        writeln!(writer, "inputs {}", format_list(&self.inputs))?;
+        writeln!(writer, "memory {}", format_program(&self.memory))
+    }
+
+    pub fn read(reader: impl BufRead) -> Result<Snapshot, Error> {
+        let mut fields = BTreeMap::new();
+
+        for (n, line) in reader.lines().enumerate() {
+            let line = line.context(Read)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (name, value) = match line.find(' ') {
+                Some(at) => (&line[..at], line[at + 1..].trim()),
+                None => (line.trim(), ""),
+            };
+            if !FIELDS.contains(&name) {
+                return Syntax {
+                    line: n + 1,
+                    message: format!("unknown field {:?}", name),
+                }
+                .fail();
+            }
+            if fields.insert(name.to_string(), value.to_string()).is_some() {
+                return Syntax {
+                    line: n + 1,
+                    message: format!("duplicate field {:?}", name),
+                }
+                .fail();
+            }
+        }
+
+        let field = |name: &str| {
+            fields
+                .get(name)
+                .map(String::as_str)
+                .context(Field { field: name })
+        };
+        let number = |name: &str| {
+            field(name)?
+                .parse::<i64>()
+                .ok()
+                .context(Field { field: name })
+        };
+        let count = |name: &str| {
+            field(name)?
+                .parse::<usize>()
+                .ok()
+                .context(Field { field: name })
+        };
+        let list = |name: &str| {
+            parse_list(field(name)?).context(Field { field: name })
+        };
+
+        Ok(Snapshot {
+            ip: count("ip")?,
+            relative_base: number("relative_base")?,
+            steps: count("steps")?,
+            halted: field("halted")?
+                .parse()
+                .ok()
+                .context(Field { field: "halted" })?,
+            memory: Memory::from(list("memory")?),
+            inputs: list("inputs")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{load_program, Machine, State};
+    use super::*;
+
+    /// Doubles each input, forever.
+    const DOUBLER: &str = "3,9,1002,9,2,9,4,9,1105,1,0";
+
+    #[test]
+    fn round_trip() {
+        let mut machine = Machine::new(load_program(DOUBLER).unwrap());
+        machine.push_input(4);
+        machine.run().unwrap();
+        machine.push_input(5);
+
+        let snapshot = machine.snapshot();
+        let mut written = vec![];
+        snapshot.write(&mut written).unwrap();
+
+        let read = Snapshot::read(&written[..]).unwrap();
+        assert_eq!(snapshot, read);
+        assert_eq!(vec![5], read.inputs);
+        assert_eq!(3, read.steps);
+    }
+
+    #[test]
+    fn restoring_carries_on() {
+        let mut machine = Machine::new(load_program(DOUBLER).unwrap());
+        machine.push_input(4);
+        machine.push_input(6);
+        machine.run().unwrap();
+
+        let mut restored = Machine::restore(machine.snapshot());
+
+        assert_eq!(machine.ip(), restored.ip());
+        assert_eq!(Ok(State::Output(12)), restored.run());
+        assert_eq!(Ok(State::NeedsInput), restored.run());
+        assert_eq!(Ok(State::Output(12)), machine.run());
+    }
+
+    #[test]
+    fn restoring_a_halted_machine() {
+        let mut machine = Machine::new(load_program("99").unwrap());
+        machine.run().unwrap();
+
+        let mut restored = Machine::restore(machine.snapshot());
+
+        assert!(restored.is_halted());
+        assert_eq!(Ok(State::Halted), restored.run());
+    }
+
+    #[test]
+    fn bad_snapshots() {
+        let text = "ip 0\nrelative_base 0\nsteps 0\nhalted no\n\
+                    inputs\nmemory 99\n";
+        assert!(matches!(
+            Snapshot::read(text.as_bytes()),
+            Err(Error::Field { field }) if field == "halted"
+        ));
+
+        assert!(matches!(
+            Snapshot::read("ip 0\nmemory 99\n".as_bytes()),
+            Err(Error::Field { field }) if field == "relative_base"
+        ));
+
+        assert!(matches!(
+            Snapshot::read("ip 0\nsp 1\n".as_bytes()),
+            Err(Error::Syntax { line: 2, .. })
+        ));
+
+        let text = "ip -1\nrelative_base 0\nsteps 0\nhalted false\n\
+                    inputs\nmemory 99\n";
+        assert!(matches!(
+            Snapshot::read(text.as_bytes()),
+            Err(Error::Field { field }) if field == "ip"
+        ));
+
+        let text = "ip 0\nrelative_base 0\nsteps -3\nhalted false\n\
+                    inputs\nmemory 99\n";
+        assert!(matches!(
+            Snapshot::read(text.as_bytes()),
+            Err(Error::Field { field }) if field == "steps"
+        ));
+
+        assert!(matches!(
+            Snapshot::read("ip 0\nsteps 1\nip 4\n".as_bytes()),
+            Err(Error::Syntax { line: 3, .. })
+        ));
+    }
+}
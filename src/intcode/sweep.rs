@@ -0,0 +1,253 @@
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use itertools::Itertools;
+
+use super::{execute_limited, Address, Limits, Memory};
+
+/// One point in a parameter space: memory patches applied before the run
+/// and the inputs fed to it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Candidate {
+    pub patches: Vec<(Address, i64)>,
+    pub inputs: Vec<i64>,
+}
+
+/// The state a successful run finished in.
+#[derive(Debug)]
+pub struct Outcome {
+    pub memory: Memory,
+    pub outputs: Vec<i64>,
+}
+
+/// Every combination of values from `range` written to `addresses`.
+pub fn patches(
+    addresses: &[Address],
+    range: RangeInclusive<i64>,
+) -> impl Iterator<Item = Candidate> {
+    let addresses = addresses.to_vec();
+
+    addresses
+        .iter()
+        .map(|_| range.clone())
+        .multi_cartesian_product()
+        .map(move |values| Candidate {
+            patches: addresses.iter().copied().zip(values).collect(),
+            inputs: vec![],
+        })
+}
+
+/// Each input vector on its own, with no memory patches.
+pub fn inputs(
+    vectors: impl IntoIterator<Item = Vec<i64>>,
+) -> impl Iterator<Item = Candidate> {
+    vectors.into_iter().map(|inputs| Candidate {
+        patches: vec![],
+        inputs,
+    })
+}
+
+fn run(
+    program: &Memory,
+    limits: Limits,
+    candidate: &Candidate,
+) -> Option<Outcome> {
+    let mut memory = program.clone();
+    for &(address, value) in &candidate.patches {
+        memory[address] = value;
+    }
+
+    let mut outputs = vec![];
+    let inputs = &mut candidate.inputs.iter();
+    execute_limited(&mut memory, inputs, &mut outputs, limits).ok()?;
+
+    Some(Outcome { memory, outputs })
+}
+
+fn threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Runs candidates from `space` across a pool of threads and returns the
+/// earliest one (in `space` order) whose outcome satisfies `predicate`.
+/// Runs that fail or exceed `limits` are skipped. Once a match is found,
+/// candidates after it are no longer started.
+pub fn find_first<I, F>(
+    program: &Memory,
+    limits: Limits,
+    space: I,
+    predicate: F,
+) -> Option<(Candidate, Outcome)>
+where
+    I: IntoIterator<Item = Candidate>,
+    I::IntoIter: Send,
+    F: Fn(&Outcome) -> bool + Sync,
+{
+    let space = Mutex::new(space.into_iter().enumerate());
+    let found_at = AtomicUsize::new(usize::MAX);
+    let found = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..threads() {
+            scope.spawn(|| loop {
+                let (index, candidate) = match space.lock().unwrap().next() {
+                    Some(next) => next,
+                    None => return,
+                };
+                if index > found_at.load(Ordering::SeqCst) {
+                    return;
+                }
+
+                let outcome = match run(program, limits, &candidate) {
+                    Some(outcome) if predicate(&outcome) => outcome,
+                    _ => continue,
+                };
+
+                let mut found = found.lock().unwrap();
+                if index < found_at.load(Ordering::SeqCst) {
+                    found_at.store(index, Ordering::SeqCst);
+                    *found = Some((candidate, outcome));
+                }
+            });
+        }
+    });
+
+    found.into_inner().unwrap()
+}
+
+/// Runs every candidate in `space` across a pool of threads and returns the
+/// one with the highest score. Runs that fail, exceed `limits` or score
+/// `None` are skipped; ties go to the earliest candidate.
+pub fn best<I, F>(
+    program: &Memory,
+    limits: Limits,
+    space: I,
+    score: F,
+) -> Option<(Candidate, i64)>
+where
+    I: IntoIterator<Item = Candidate>,
+    I::IntoIter: Send,
+    F: Fn(&Outcome) -> Option<i64> + Sync,
+{
+    let space = Mutex::new(space.into_iter().enumerate());
+    let best = Mutex::new(None::<(i64, usize, Candidate)>);
+
+    thread::scope(|scope| {
+        for _ in 0..threads() {
+            scope.spawn(|| loop {
+                let (index, candidate) = match space.lock().unwrap().next() {
+                    Some(next) => next,
+                    None => return,
+                };
+
+                let value = match run(program, limits, &candidate)
+                    .and_then(|o| score(&o))
+                {
+                    Some(value) => value,
+                    None => continue,
+                };
+
+                let mut best = best.lock().unwrap();
+                let better = best.as_ref().map_or(true, |(v, i, _)| {
+                    value > *v || (value == *v && index < *i)
+                });
+                if better {
+                    *best = Some((value, index, candidate));
+                }
+            });
+        }
+    });
+
+    best.into_inner()
+        .unwrap()
+        .map(|(value, _, candidate)| (candidate, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::load_program;
+
+    #[test]
+    fn day2() {
+        let program =
+            load_program(&std::fs::read_to_string("inputs/2.txt").unwrap())
+                .unwrap();
+
+        let (candidate, outcome) = find_first(
+            &program,
+            Limits::default(),
+            patches(&[1, 2], 0..=99),
+            |o| o.memory[0] == 19690720,
+        )
+        .unwrap();
+
+        assert_eq!(candidate.patches, vec![(1, 50), (2, 64)]);
+        assert_eq!(outcome.memory[0], 19690720);
+    }
+
+    #[test]
+    fn first_in_order() {
+        // Doubles the input in place
+        let program = load_program("3,0,1,0,0,0,99").unwrap();
+        let space = inputs((0..1000).map(|n| vec![n]));
+
+        let (candidate, outcome) =
+            find_first(&program, Limits::default(), space, |o| {
+                o.memory[0] == 8
+            })
+            .unwrap();
+
+        assert_eq!(candidate.inputs, vec![4]);
+        assert_eq!(outcome.outputs, vec![]);
+    }
+
+    #[test]
+    fn best_score() {
+        // Outputs twice the input
+        let program = load_program("3,0,1,0,0,1,4,1,99").unwrap();
+        let space = inputs((-5..=5).map(|n| vec![n]));
+
+        let (candidate, value) =
+            best(&program, Limits::default(), space, |o| {
+                o.outputs.first().map(|v| -v)
+            })
+            .unwrap();
+
+        assert_eq!(candidate.inputs, vec![-5]);
+        assert_eq!(value, 10);
+    }
+
+    #[test]
+    fn skips_runaways() {
+        // Loops forever unless the input is zero
+        let program = load_program("3,7,1005,7,2,99,0,0").unwrap();
+        let limits = Limits {
+            steps: None,
+            cycle_interval: Some(100),
+        };
+        let space = inputs(vec![vec![1], vec![0]]);
+
+        let (candidate, _) =
+            find_first(&program, limits, space, |_| true).unwrap();
+        assert_eq!(candidate.inputs, vec![0]);
+    }
+
+    #[test]
+    fn skips_failures() {
+        let program = load_program("3,0,99").unwrap();
+        let space = inputs(vec![vec![], vec![7]]);
+
+        let (candidate, _) =
+            find_first(&program, Limits::default(), space, |_| true).unwrap();
+        assert_eq!(candidate.inputs, vec![7]);
+        assert_eq!(
+            best(&program, Limits::default(), inputs(vec![vec![]]), |_| Some(
+                0
+            )),
+            None
+        );
+    }
+}
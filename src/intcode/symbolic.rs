@@ -0,0 +1,515 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use itertools::Itertools;
+use snafu::Snafu;
+
+use super::{address_of, next_op, Address, Memory, Op, Parameter, Reason};
+
+const STEP_LIMIT: usize = 1_000_000;
+const CYCLE_INTERVAL: usize = 1000;
+
+/// A constant plus a coefficient for each unknown memory cell.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Linear {
+    pub constant: i64,
+    terms: BTreeMap<Address, i64>,
+}
+
+impl Linear {
+    fn constant(value: i64) -> Linear {
+        Linear {
+            constant: value,
+            terms: BTreeMap::new(),
+        }
+    }
+
+    fn unknown(address: Address) -> Linear {
+        let mut terms = BTreeMap::new();
+        terms.insert(address, 1);
+        Linear { constant: 0, terms }
+    }
+
+    /// The coefficient of the unknown at `address`.
+    pub fn coefficient(&self, address: Address) -> i64 {
+        self.terms.get(&address).copied().unwrap_or(0)
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        if self.terms.is_empty() {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    fn add(&self, other: &Linear) -> Linear {
+        let mut terms = self.terms.clone();
+        for (&address, &k) in &other.terms {
+            *terms.entry(address).or_insert(0) += k;
+        }
+        terms.retain(|_, k| *k != 0);
+
+        Linear {
+            constant: self.constant + other.constant,
+            terms,
+        }
+    }
+
+    fn scale(&self, factor: i64) -> Linear {
+        let mut terms: BTreeMap<_, _> =
+            self.terms.iter().map(|(&a, &k)| (a, k * factor)).collect();
+        terms.retain(|_, k| *k != 0);
+
+        Linear {
+            constant: self.constant * factor,
+            terms,
+        }
+    }
+
+    fn mul(&self, other: &Linear) -> Option<Linear> {
+        match (self.as_constant(), other.as_constant()) {
+            (Some(k), _) => Some(other.scale(k)),
+            (_, Some(k)) => Some(self.scale(k)),
+            _ => None,
+        }
+    }
+
+    /// Evaluates the expression with `values[i]` standing in for the i-th
+    /// address of `unknowns`.
+    pub fn eval(&self, unknowns: &[Address], values: &[i64]) -> i64 {
+        self.constant
+            + unknowns
+                .iter()
+                .zip(values)
+                .map(|(&address, value)| self.coefficient(address) * value)
+                .sum::<i64>()
+    }
+}
+
+impl fmt::Display for Linear {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (n, (address, k)) in self.terms.iter().enumerate() {
+            match (n, *k) {
+                (0, 1) => write!(f, "[{}]", address)?,
+                (0, -1) => write!(f, "-[{}]", address)?,
+                (0, k) => write!(f, "{}*[{}]", k, address)?,
+                (_, 1) => write!(f, " + [{}]", address)?,
+                (_, -1) => write!(f, " - [{}]", address)?,
+                (_, k) if k < 0 => write!(f, " - {}*[{}]", -k, address)?,
+                (_, k) => write!(f, " + {}*[{}]", k, address)?,
+            }
+        }
+
+        match (self.terms.is_empty(), self.constant) {
+            (true, c) => write!(f, "{}", c),
+            (false, 0) => Ok(()),
+            (false, c) if c < 0 => write!(f, " - {}", -c),
+            (false, c) => write!(f, " + {}", c),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Sym {
+    Known(Linear),
+    /// Depends on the unknowns in a way we can't track.
+    Opaque,
+}
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Instruction at ip {} depends on the unknowns", ip))]
+    SymbolicInstruction { ip: Address },
+    #[snafu(display("Write at ip {} goes to an unknown address", ip))]
+    SymbolicAddress { ip: Address },
+    #[snafu(display("Control flow at ip {} depends on the unknowns", ip))]
+    SymbolicBranch { ip: Address },
+    #[snafu(display(
+        "Relative base adjustment at ip {} depends on the unknowns",
+        ip
+    ))]
+    SymbolicRelativeBase { ip: Address },
+    #[snafu(display("Failure at ip {}: {}", ip, reason))]
+    Execution { ip: Address, reason: Reason },
+    #[snafu(display("Gave up after {} steps", steps))]
+    StepLimit { steps: usize },
+    #[snafu(display("Address {} isn't linear in the unknowns", address))]
+    Nonlinear { address: Address },
+}
+
+struct Evaluator<'a> {
+    mem: &'a Memory,
+    cells: HashMap<Address, Sym>,
+    ip: Address,
+    relative_base: i64,
+}
+
+impl<'a> Evaluator<'a> {
+    fn get(&self, address: Address) -> Sym {
+        self.cells
+            .get(&address)
+            .cloned()
+            .unwrap_or_else(|| Sym::Known(Linear::constant(self.mem[address])))
+    }
+
+    fn concrete(&self, address: Address) -> Option<i64> {
+        match self.get(address) {
+            Sym::Known(l) => l.as_constant(),
+            Sym::Opaque => None,
+        }
+    }
+
+    fn fail(&self, reason: Reason) -> Error {
+        Error::Execution {
+            ip: self.ip,
+            reason,
+        }
+    }
+
+    /// Decodes the instruction at ip, substituting a placeholder for any
+    /// operand word that depends on the unknowns.
+    fn decode(&self) -> Result<(Op, Vec<Option<Sym>>), Error> {
+        let ip = self.ip;
+        let opcode =
+            self.concrete(ip).ok_or(Error::SymbolicInstruction { ip })?;
+
+        let mut words = vec![opcode];
+        let mut symbolic = vec![];
+        for address in ip + 1..ip + 4 {
+            match self.concrete(address) {
+                Some(word) => {
+                    words.push(word);
+                    symbolic.push(None)
+                }
+                None => {
+                    words.push(0);
+                    symbolic.push(Some(self.get(address)))
+                }
+            }
+        }
+
+        let op = next_op(&words).map_err(|r| self.fail(r))?;
+        Ok((op, symbolic))
+    }
+
+    fn step(&mut self) -> Result<bool, Error> {
+        use Op::*;
+
+        let ip = self.ip;
+        let (op, symbolic) = self.decode()?;
+        let base = self.relative_base;
+
+        let sources: Vec<Sym> = op
+            .sources()
+            .into_iter()
+            .zip(&symbolic)
+            .map(|(p, word)| match (p, word) {
+                (Parameter::Immediate(_), Some(sym)) => Ok(sym.clone()),
+                (_, Some(_)) => Ok(Sym::Opaque),
+                (Parameter::Immediate(v), None) => {
+                    Ok(Sym::Known(Linear::constant(*v)))
+                }
+                (p, None) => address_of(p, base)
+                    .map(|a| self.get(a))
+                    .map_err(|r| self.fail(r)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let dest = match op.dest() {
+            Some(_) if symbolic[op.size() - 2].is_some() => {
+                return Err(Error::SymbolicAddress { ip })
+            }
+            Some(p) => Some(address_of(p, base).map_err(|r| self.fail(r))?),
+            None => None,
+        };
+
+        let known = |n: usize| match &sources[n] {
+            Sym::Known(l) => l.as_constant(),
+            Sym::Opaque => None,
+        };
+        let binary = |f: &dyn Fn(&Linear, &Linear) -> Option<Linear>| match (
+            &sources[0],
+            &sources[1],
+        ) {
+            (Sym::Known(a), Sym::Known(b)) => {
+                f(a, b).map_or(Sym::Opaque, Sym::Known)
+            }
+            _ => Sym::Opaque,
+        };
+        let compare = |f: fn(i64, i64) -> bool| match (known(0), known(1)) {
+            (Some(a), Some(b)) => Sym::Known(Linear::constant(f(a, b) as i64)),
+            _ => Sym::Opaque,
+        };
+
+        let value = match op {
+            Halt => return Ok(false),
+            Add { .. } => binary(&|a, b| Some(a.add(b))),
+            Mul { .. } => binary(&|a, b| a.mul(b)),
+            LessThan { .. } => compare(|a, b| a < b),
+            Equals { .. } => compare(|a, b| a == b),
+            Input { .. } => return Err(self.fail(Reason::MissingInput)),
+            Output { .. } => {
+                self.ip += op.size();
+                return Ok(true);
+            }
+            JumpIfTrue { .. } | JumpIfFalse { .. } => {
+                let cmp = known(0).ok_or(Error::SymbolicBranch { ip })?;
+                if (cmp != 0) == matches!(op, JumpIfTrue { .. }) {
+                    let target =
+                        known(1).ok_or(Error::SymbolicBranch { ip })?;
+                    self.ip = Address::try_from(target)
+                        .map_err(|_| self.fail(Reason::InvalidJump(target)))?;
+                } else {
+                    self.ip += op.size();
+                }
+                return Ok(true);
+            }
+            AdjustRelativeBase { .. } => {
+                self.relative_base +=
+                    known(0).ok_or(Error::SymbolicRelativeBase { ip })?;
+                self.ip += op.size();
+                return Ok(true);
+            }
+        };
+
+        self.cells.insert(dest.unwrap(), value);
+        self.ip += op.size();
+        Ok(true)
+    }
+}
+
+/// Runs `mem` with the cells at `unknowns` left symbolic and returns the
+/// final contents of `target` as a linear expression in those cells.
+pub fn evaluate(
+    mem: &Memory,
+    unknowns: &[Address],
+    target: Address,
+) -> Result<Linear, Error> {
+    let mut evaluator = Evaluator {
+        mem,
+        cells: unknowns
+            .iter()
+            .map(|&a| (a, Sym::Known(Linear::unknown(a))))
+            .collect(),
+        ip: 0,
+        relative_base: 0,
+    };
+
+    let mut steps = 0;
+    while evaluator.step()? {
+        steps += 1;
+        if steps >= STEP_LIMIT {
+            return Err(Error::StepLimit { steps });
+        }
+    }
+
+    match evaluator.get(target) {
+        Sym::Known(l) => Ok(l),
+        Sym::Opaque => Err(Error::Nonlinear { address: target }),
+    }
+}
+
+/// A memory cell to solve for and the values it may take.
+#[derive(Debug, Clone)]
+pub struct Unknown {
+    pub address: Address,
+    pub range: RangeInclusive<i64>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Method {
+    Symbolic(Linear),
+    /// Symbolic evaluation failed for the given reason.
+    BruteForce(Error),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Solution {
+    pub values: Vec<i64>,
+    pub method: Method,
+}
+
+/// Finds values for `unknowns` that leave `want` at `target` once the
+/// program halts. Solves the linear expression directly where possible and
+/// otherwise tries every combination.
+pub fn solve(
+    mem: &Memory,
+    unknowns: &[Unknown],
+    target: Address,
+    want: i64,
+) -> Option<Solution> {
+    let addresses: Vec<_> = unknowns.iter().map(|u| u.address).collect();
+
+    match evaluate(mem, &addresses, target) {
+        Ok(linear) => {
+            solve_linear(&linear, unknowns, want).map(|values| Solution {
+                values,
+                method: Method::Symbolic(linear),
+            })
+        }
+        Err(e) => {
+            brute_force(mem, unknowns, target, want).map(|values| Solution {
+                values,
+                method: Method::BruteForce(e),
+            })
+        }
+    }
+}
+
+/// Enumerates all but the last unknown and solves for the last one.
+fn solve_linear(
+    linear: &Linear,
+    unknowns: &[Unknown],
+    want: i64,
+) -> Option<Vec<i64>> {
+    let addresses: Vec<_> = unknowns.iter().map(|u| u.address).collect();
+    let (last, rest) = unknowns.split_last()?;
+    let k = linear.coefficient(last.address);
+
+    rest.iter()
+        .map(|u| u.range.clone())
+        .multi_cartesian_product()
+        .chain(if rest.is_empty() { Some(vec![]) } else { None })
+        .find_map(|mut values| {
+            values.push(0);
+            let remainder = want - linear.eval(&addresses, &values);
+            let value = match k {
+                0 if remainder == 0 => *last.range.start(),
+                0 => return None,
+                k if remainder % k == 0 => remainder / k,
+                _ => return None,
+            };
+
+            if last.range.contains(&value) {
+                *values.last_mut().unwrap() = value;
+                Some(values)
+            } else {
+                None
+            }
+        })
+}
+
+fn brute_force(
+    mem: &Memory,
+    unknowns: &[Unknown],
+    target: Address,
+    want: i64,
+) -> Option<Vec<i64>> {
+    unknowns
+        .iter()
+        .map(|u| u.range.clone())
+        .multi_cartesian_product()
+        .find(|values| {
+            let mut mem = mem.clone();
+            for (u, &value) in unknowns.iter().zip(values) {
+                mem[u.address] = value;
+            }
+
+            // Patches that leave the program running forever aren't
+            // solutions either.
+            let limits = super::Limits {
+                steps: Some(STEP_LIMIT),
+                cycle_interval: Some(CYCLE_INTERVAL),
+            };
+            let inputs: [i64; 0] = [];
+            let mut outputs = vec![];
+            let halted = super::execute_limited(
+                &mut mem,
+                &mut inputs.iter(),
+                &mut outputs,
+                limits,
+            )
+            .is_ok();
+
+            halted && mem[target] == want
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::load_program;
+
+    fn unknowns(addresses: &[Address]) -> Vec<Unknown> {
+        addresses
+            .iter()
+            .map(|&address| Unknown {
+                address,
+                range: 0..=99,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn day2_is_linear() {
+        let mem =
+            load_program(&std::fs::read_to_string("inputs/2.txt").unwrap())
+                .unwrap();
+
+        let linear = evaluate(&mem, &[1, 2], 0).unwrap();
+        assert_eq!(linear.eval(&[1, 2], &[12, 2]), 5_098_658);
+
+        let solution = solve(&mem, &unknowns(&[1, 2]), 0, 19_690_720).unwrap();
+        assert_eq!(solution.values, vec![50, 64]);
+        assert!(matches!(solution.method, Method::Symbolic(_)));
+    }
+
+    #[test]
+    fn displays_expression() {
+        // [0] = [9] * 3 + [11]
+        let mem = load_program("2,9,10,0,1,0,11,0,99,0,3,0").unwrap();
+        let linear = evaluate(&mem, &[9, 11], 0).unwrap();
+
+        assert_eq!(linear.to_string(), "3*[9] + [11]");
+    }
+
+    #[test]
+    fn falls_back_when_nonlinear() {
+        // [0] = [5] * [6]
+        let mem = load_program("2,5,6,0,99,0,0").unwrap();
+
+        assert_eq!(
+            evaluate(&mem, &[5, 6], 0),
+            Err(Error::Nonlinear { address: 0 })
+        );
+
+        let solution = solve(&mem, &unknowns(&[5, 6]), 0, 6).unwrap();
+        assert_eq!(solution.values, vec![1, 6]);
+        assert!(matches!(solution.method, Method::BruteForce(_)));
+    }
+
+    #[test]
+    fn skips_patches_that_never_halt() {
+        // Jumps back to itself forever unless [1] is non-zero, then sets
+        // [8] = 2 + 3.
+        let mem = load_program("1106,0,0,1101,2,3,8,99,0").unwrap();
+
+        let solution = solve(&mem, &unknowns(&[1]), 8, 5).unwrap();
+        assert_eq!(solution.values, vec![1]);
+        assert!(matches!(solution.method, Method::BruteForce(_)));
+    }
+
+    #[test]
+    fn no_solution() {
+        // [0] = 2 * [5]
+        let mem = load_program("1,5,5,0,99,0").unwrap();
+        let unknowns = unknowns(&[5]);
+
+        assert_eq!(solve(&mem, &unknowns, 0, 7), None);
+        assert_eq!(solve(&mem, &unknowns, 0, 8).unwrap().values, vec![4]);
+    }
+
+    #[test]
+    fn symbolic_relative_base() {
+        // arb [3], with [3] unknown
+        let mem = load_program("9,3,99,0").unwrap();
+
+        assert_eq!(
+            evaluate(&mem, &[3], 0),
+            Err(Error::SymbolicRelativeBase { ip: 0 })
+        );
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{self, BufRead};
+
+use snafu::{OptionExt, ResultExt, Snafu};
+
+use super::{Address, Memory};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read trace: {}", source))]
+    Read { source: io::Error },
+    #[snafu(display("Line {}: {}", line, message))]
+    Syntax { line: usize, message: String },
+    #[snafu(display("Line {}: missing or invalid field {:?}", line, field))]
+    Field { line: usize, field: String },
+    #[snafu(display("Trace is empty"))]
+    Empty,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Write {
+    pub address: Address,
+    pub old: i64,
+    pub new: i64,
+}
+
+/// Everything observable about one executed instruction.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Event {
+    pub step: usize,
+    pub ip: Address,
+    pub op: String,
+    pub operands: Vec<i64>,
+    pub write: Option<Write>,
+    pub input: Option<i64>,
+    pub output: Option<i64>,
+}
+
+pub trait Tracer {
+    /// Lets the interpreter skip building events nobody will look at.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn record(&mut self, event: &Event);
+}
+
+/// The tracer that isn't one.
+impl Tracer for () {
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    fn record(&mut self, _: &Event) {}
+}
+
+impl Tracer for Vec<Event> {
+    fn record(&mut self, event: &Event) {
+        self.push(event.clone());
+    }
+}
+
+impl<T: Tracer + ?Sized> Tracer for &mut T {
+    fn enabled(&self) -> bool {
+        (**self).enabled()
+    }
+
+    fn record(&mut self, event: &Event) {
+        (**self).record(event);
+    }
+}
+
+/// Sends every event to both tracers.
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+    fn enabled(&self) -> bool {
+        self.0.enabled() || self.1.enabled()
+    }
+
+    fn record(&mut self, event: &Event) {
+        self.0.record(event);
+        self.1.record(event);
+    }
+}
+
+fn json_list(values: &[i64]) -> String {
+    let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Writes a JSON-lines trace: a header line holding the initial memory,
+/// then one line per executed instruction. A failed write doesn't stop the
+/// program; tracing stops instead and `finish` reports the error.
+pub struct JsonLines<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> JsonLines<W> {
+    pub fn new(mut writer: W, initial: &Memory) -> io::Result<JsonLines<W>> {
+        writeln!(writer, "{{\"memory\":{}}}", json_list(&initial.to_vec()))?;
+
+        Ok(JsonLines {
+            writer,
+            error: None,
+        })
+    }
+
+    /// Flushes the trace and hands back the writer, or the first error
+    /// seen while writing.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error.take() {
+            Some(e) => Err(e),
+            None => {
+                self.writer.flush()?;
+                Ok(self.writer)
+            }
+        }
+    }
+}
+
+impl<W: io::Write> Tracer for JsonLines<W> {
+    fn enabled(&self) -> bool {
+        self.error.is_none()
+    }
+
+    fn record(&mut self, event: &Event) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let mut fields = vec![
+            format!("\"step\":{}", event.step),
+            format!("\"ip\":{}", event.ip),
+            format!("\"op\":{}", json_string(&event.op)),
+            format!("\"operands\":{}", json_list(&event.operands)),
+        ];
+
+        if let Some(w) = event.write {
+            fields.push(format!(
+                "\"write\":{}",
+                json_list(&[w.address as i64, w.old, w.new])
+            ));
+        }
+        if let Some(input) = event.input {
+            fields.push(format!("\"input\":{}", input));
+        }
+        if let Some(output) = event.output {
+            fields.push(format!("\"output\":{}", output));
+        }
+
+        if let Err(e) = writeln!(self.writer, "{{{}}}", fields.join(",")) {
+            self.error = Some(e);
+        }
+    }
+}
+
+/// The subset of JSON values a trace uses.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Number(i64),
+    Str(String),
+    List(Vec<i64>),
+}
+
+/// Parses one flat trace object. Anything fancier than what `JsonLines`
+/// writes is rejected.
+fn parse_object(text: &str) -> Result<BTreeMap<String, Json>, String> {
+    let mut chars = text.trim().chars().peekable();
+    let mut fields = BTreeMap::new();
+
+    fn skip_ws(chars: &mut std::iter::Peekable<std::str::Chars>) {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+    }
+
+    fn expect(
+        chars: &mut std::iter::Peekable<std::str::Chars>,
+        want: char,
+    ) -> Result<(), String> {
+        skip_ws(chars);
+        match chars.next() {
+            Some(c) if c == want => Ok(()),
+            other => Err(format!("expected {:?}, found {:?}", want, other)),
+        }
+    }
+
+    fn string(
+        chars: &mut std::iter::Peekable<std::str::Chars>,
+    ) -> Result<String, String> {
+        expect(chars, '"')?;
+        let mut s = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.extend(chars.next()),
+                Some(c) => s.push(c),
+                None => return Err("unterminated string".to_string()),
+            }
+        }
+    }
+
+    fn number(
+        chars: &mut std::iter::Peekable<std::str::Chars>,
+    ) -> Result<i64, String> {
+        skip_ws(chars);
+        let mut digits = String::new();
+        while chars
+            .peek()
+            .map_or(false, |c| *c == '-' || c.is_ascii_digit())
+        {
+            digits.extend(chars.next());
+        }
+        digits
+            .parse()
+            .map_err(|_| format!("bad number {:?}", digits))
+    }
+
+    expect(&mut chars, '{')?;
+    skip_ws(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+        return Ok(fields);
+    }
+
+    loop {
+        skip_ws(&mut chars);
+        let key = string(&mut chars)?;
+        expect(&mut chars, ':')?;
+        skip_ws(&mut chars);
+
+        let value = match chars.peek() {
+            Some('"') => Json::Str(string(&mut chars)?),
+            Some('[') => {
+                chars.next();
+                let mut values = vec![];
+                skip_ws(&mut chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                } else {
+                    loop {
+                        values.push(number(&mut chars)?);
+                        skip_ws(&mut chars);
+                        match chars.next() {
+                            Some(',') => (),
+                            Some(']') => break,
+                            other => {
+                                return Err(format!(
+                                    "expected ',' or ']', found {:?}",
+                                    other
+                                ))
+                            }
+                        }
+                    }
+                }
+                Json::List(values)
+            }
+            _ => Json::Number(number(&mut chars)?),
+        };
+        fields.insert(key, value);
+
+        skip_ws(&mut chars);
+        match chars.next() {
+            Some(',') => (),
+            Some('}') => return Ok(fields),
+            other => {
+                return Err(format!("expected ',' or '}}', found {:?}", other))
+            }
+        }
+    }
+}
+
+fn parse_event(line: usize, text: &str) -> Result<Event, Error> {
+    let fields = parse_object(text)
+        .map_err(|message| Error::Syntax { line, message })?;
+
+    let number = |field: &str| match fields.get(field) {
+        Some(Json::Number(n)) => Some(*n),
+        _ => None,
+    };
+    let count = |field: &str| {
+        number(field)
+            .and_then(|n| usize::try_from(n).ok())
+            .context(Field { line, field })
+    };
+
+    let op = match fields.get("op") {
+        Some(Json::Str(op)) => op.clone(),
+        _ => return Field { line, field: "op" }.fail(),
+    };
+
+    let operands = match fields.get("operands") {
+        Some(Json::List(values)) => values.clone(),
+        _ => {
+            return Field {
+                line,
+                field: "operands",
+            }
+            .fail()
+        }
+    };
+
+    let write = match fields.get("write") {
+        Some(Json::List(w)) if w.len() == 3 && w[0] >= 0 => Some(Write {
+            address: w[0] as Address,
+            old: w[1],
+            new: w[2],
+        }),
+        None => None,
+        _ => {
+            return Field {
+                line,
+                field: "write",
+            }
+            .fail()
+        }
+    };
+
+    Ok(Event {
+        step: count("step")?,
+        ip: count("ip")?,
+        op,
+        operands,
+        write,
+        input: number("input"),
+        output: number("output"),
+    })
+}
+
+/// A recorded trace that can rebuild memory as it was before any step.
+#[derive(Debug)]
+pub struct Replay {
+    initial: Memory,
+    events: Vec<Event>,
+}
+
+impl Replay {
+    pub fn new(initial: Memory, events: Vec<Event>) -> Replay {
+        Replay { initial, events }
+    }
+
+    pub fn read(reader: impl BufRead) -> Result<Replay, Error> {
+        let mut lines = reader.lines();
+
+        let header = lines.next().context(Empty)?.context(Read)?;
+        let initial = match parse_object(&header)
+            .map_err(|message| Error::Syntax { line: 1, message })?
+            .remove("memory")
+        {
+            Some(Json::List(values)) => Memory::from(values),
+            _ => {
+                return Field {
+                    line: 1usize,
+                    field: "memory",
+                }
+                .fail()
+            }
+        };
+
+        let events = lines
+            .enumerate()
+            .map(|(n, text)| parse_event(n + 2, &text.context(Read)?))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Replay::new(initial, events))
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn event(&self, step: usize) -> Option<&Event> {
+        self.events.get(step)
+    }
+
+    /// Memory just before `step` executed. Steps past the end give the
+    /// final memory.
+    pub fn memory_at(&self, step: usize) -> Memory {
+        let mut mem = self.initial.clone();
+
+        for event in self.events.iter().take(step) {
+            if let Some(write) = event.write {
+                mem[write.address] = write.new;
+            }
+        }
+
+        mem
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{asm::assemble, execute_traced};
+    use super::*;
+
+    fn program() -> Memory {
+        assemble(
+            "in [10]
+             mul [10], #2, [11]
+             out [11]
+             halt
+             .data 0, 0, 0",
+        )
+        .unwrap()
+    }
+
+    fn trace() -> (Vec<Event>, Vec<u8>) {
+        let mut mem = program();
+        let mut events = vec![];
+        let mut json = JsonLines::new(vec![], &mem).unwrap();
+        let mut outputs = vec![];
+
+        execute_traced(
+            &mut mem,
+            &mut [21].iter(),
+            &mut outputs,
+            &mut (&mut events, &mut json),
+        )
+        .unwrap();
+
+        (events, json.finish().unwrap())
+    }
+
+    #[test]
+    fn recording() {
+        let (events, _) = trace();
+
+        assert_eq!(4, events.len());
+        assert_eq!(
+            Event {
+                step: 1,
+                ip: 2,
+                op: "mul [10], #2, [11]".to_string(),
+                operands: vec![21, 2],
+                write: Some(Write {
+                    address: 11,
+                    old: 0,
+                    new: 42
+                }),
+                input: None,
+                output: None,
+            },
+            events[1]
+        );
+        assert_eq!(Some(21), events[0].input);
+        assert_eq!(Some(42), events[2].output);
+    }
+
+    #[test]
+    fn failed_instructions_arent_recorded() {
+        // The output reads from relative base - 1.
+        let mut mem = assemble("out #1\nout [rb-1]\nhalt").unwrap();
+        let mut events = vec![];
+        let mut outputs = vec![];
+
+        assert!(execute_traced(
+            &mut mem,
+            &mut [].iter(),
+            &mut outputs,
+            &mut events
+        )
+        .is_err());
+        assert_eq!(1, events.len());
+    }
+
+    #[test]
+    fn json_lines() {
+        let (_, json) = trace();
+        let text = String::from_utf8(json).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!("{\"memory\":[3,10,1002,10,2,11,4,11,99,0,0,0]}", lines[0]);
+        assert_eq!(
+            "{\"step\":0,\"ip\":0,\"op\":\"in [10]\",\"operands\":[],\
+             \"write\":[10,0,21],\"input\":21}",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn replaying() {
+        let (events, json) = trace();
+        let replay = Replay::read(&json[..]).unwrap();
+
+        assert_eq!(events.len(), replay.len());
+        assert_eq!(Some(&events[2]), replay.event(2));
+        assert_eq!(program(), replay.memory_at(0));
+        assert_eq!(21, replay.memory_at(1)[10]);
+        assert_eq!(0, replay.memory_at(1)[11]);
+        assert_eq!(42, replay.memory_at(2)[11]);
+        assert_eq!(replay.memory_at(4), replay.memory_at(100));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(matches!(
+            Replay::read(&b"{\"memory\":[1,2]}\n{\"step\":x}\n"[..]),
+            Err(Error::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            Replay::read(&b"{\"memory\":[1,2]}\n{\"step\":0}\n"[..]),
+            Err(Error::Field { line: 2, .. })
+        ));
+
+        let json = b"{\"memory\":[1,2]}\n\
+                     {\"step\":0,\"ip\":-1,\"op\":\"halt\",\"operands\":[]}\n";
+        assert!(matches!(
+            Replay::read(&json[..]),
+            Err(Error::Field { line: 2, field }) if field == "ip"
+        ));
+    }
+
+    #[test]
+    fn write_errors_dont_stop_the_program() {
+        let mut mem = program();
+        // Room for the header but not much else.
+        let mut buffer = [0u8; 64];
+        let mut json = JsonLines::new(&mut buffer[..], &mem).unwrap();
+        let mut outputs = vec![];
+
+        execute_traced(&mut mem, &mut [21].iter(), &mut outputs, &mut json)
+            .unwrap();
+
+        assert_eq!(vec![42], outputs);
+        assert_eq!(io::ErrorKind::WriteZero, json.finish().unwrap_err().kind());
+    }
+}
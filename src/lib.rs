@@ -1,2 +1,12 @@
+pub mod adventure;
+pub mod amplifiers;
+pub mod arcade;
 pub mod crossed_wires;
+pub mod droid;
+pub mod grid;
 pub mod intcode;
+pub mod network;
+pub mod painter;
+pub mod scaffold;
+pub mod springdroid;
+pub mod tractor;
@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+use snafu::{ResultExt, Snafu};
+
+use crate::intcode::{self, Machine, Memory, State};
+
+/// The address the NAT listens on.
+pub const NAT: i64 = 255;
+
+/// Rounds in a row with no traffic before the network counts as idle.
+/// Machines poll for packets, so one quiet round can just mean a machine
+/// hasn't got round to sending yet.
+const IDLE_ROUNDS: usize = 2;
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Machine {} failed: {}", address, source))]
+    Intcode {
+        address: usize,
+        source: intcode::Error,
+    },
+    #[snafu(display("Machine {} sent to unknown address {}", from, dest))]
+    UnknownAddress { from: usize, dest: i64 },
+    #[snafu(display("Network went idle with nothing for the NAT to send"))]
+    Stalled,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Packet {
+    pub dest: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Event {
+    Sent {
+        from: usize,
+        packet: Packet,
+    },
+    /// The network was idle, so the NAT resent its last packet to 0.
+    Wake(Packet),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    machine: Machine,
+    queue: VecDeque<Packet>,
+    pending: Vec<i64>,
+}
+
+/// A set of machines that talk to each other in packets.
+///
+/// Each round, every machine in address order gets its queued packets (or
+/// -1 if there are none) and runs until it wants more input. Packets are
+/// delivered as soon as they're sent, so the whole run is deterministic.
+#[derive(Debug, Clone)]
+pub struct Network {
+    nodes: Vec<Node>,
+    nat: Option<Packet>,
+    idle_rounds: usize,
+    rounds: usize,
+}
+
+impl Network {
+    /// Boots `size` copies of `program`, giving each its address as the
+    /// first input.
+    pub fn new(program: &Memory, size: usize) -> Network {
+        let nodes = (0..size)
+            .map(|address| {
+                let mut machine = Machine::new(program.clone());
+                machine.push_input(address as i64);
+
+                Node {
+                    machine,
+                    queue: VecDeque::new(),
+                    pending: vec![],
+                }
+            })
+            .collect();
+
+        Network {
+            nodes,
+            nat: None,
+            idle_rounds: 0,
+            rounds: 0,
+        }
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// The last packet the NAT received.
+    pub fn nat(&self) -> Option<Packet> {
+        self.nat
+    }
+
+    fn deliver(&mut self, from: usize, packet: Packet) -> Result<(), Error> {
+        if packet.dest == NAT {
+            self.nat = Some(packet);
+            return Ok(());
+        }
+
+        let node = usize::try_from(packet.dest)
+            .ok()
+            .and_then(|dest| self.nodes.get_mut(dest))
+            .ok_or(Error::UnknownAddress {
+                from,
+                dest: packet.dest,
+            })?;
+        node.queue.push_back(packet);
+
+        Ok(())
+    }
+
+    /// Runs every machine once and returns the packets sent along the way.
+    pub fn round(&mut self) -> Result<Vec<Event>, Error> {
+        let mut events = vec![];
+        let mut idle = true;
+
+        for address in 0..self.nodes.len() {
+            let node = &mut self.nodes[address];
+            if node.machine.is_halted() {
+                continue;
+            }
+
+            if node.queue.is_empty() {
+                node.machine.push_input(-1);
+            } else {
+                idle = false;
+                for packet in node.queue.drain(..) {
+                    node.machine.push_input(packet.x);
+                    node.machine.push_input(packet.y);
+                }
+            }
+
+            let mut sent = vec![];
+            while let State::Output(value) =
+                node.machine.run().context(Intcode { address })?
+            {
+                node.pending.push(value);
+                if let [dest, x, y] = node.pending[..] {
+                    sent.push(Packet { dest, x, y });
+                    node.pending.clear();
+                }
+            }
+
+            for packet in sent {
+                idle = false;
+                events.push(Event::Sent {
+                    from: address,
+                    packet,
+                });
+                self.deliver(address, packet)?;
+            }
+        }
+
+        self.rounds += 1;
+        self.idle_rounds = if idle { self.idle_rounds + 1 } else { 0 };
+
+        if self.idle_rounds >= IDLE_ROUNDS {
+            let packet = Packet {
+                dest: 0,
+                ..self.nat.ok_or(Error::Stalled)?
+            };
+
+            if let Some(node) = self.nodes.first_mut() {
+                node.queue.push_back(packet);
+            }
+            events.push(Event::Wake(packet));
+            self.idle_rounds = 0;
+        }
+
+        Ok(events)
+    }
+}
+
+/// The first packet any machine sends to the NAT.
+pub fn first_nat_packet(
+    program: &Memory,
+    size: usize,
+) -> Result<Packet, Error> {
+    let mut network = Network::new(program, size);
+
+    loop {
+        for event in network.round()? {
+            if let Event::Sent { packet, .. } = event {
+                if packet.dest == NAT {
+                    return Ok(packet);
+                }
+            }
+        }
+    }
+}
+
+/// The first y value the NAT sends to machine 0 twice in a row.
+pub fn first_repeated_wake(
+    program: &Memory,
+    size: usize,
+) -> Result<i64, Error> {
+    let mut network = Network::new(program, size);
+    let mut last = None;
+
+    loop {
+        for event in network.round()? {
+            if let Event::Wake(packet) = event {
+                if last == Some(packet.y) {
+                    return Ok(packet.y);
+                }
+                last = Some(packet.y);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    /// Machine 0 starts a packet round a ring of three; each machine bumps
+    /// x and passes it on, and the last one sends it to the NAT.
+    fn ring() -> Memory {
+        assemble(
+            "       in [addr]
+                    jt [addr], #loop
+                    out #1
+                    out #7
+                    out #5
+             loop:  in [x]
+                    eq [x], #-1, [t]
+                    jt [t], #loop
+                    in [y]
+                    add [addr], #1, [dest]
+                    eq [dest], #3, [t]
+                    jf [t], #send
+                    add #255, #0, [dest]
+             send:  out [dest]
+                    add [x], #1, [x]
+                    out [x]
+                    out [y]
+                    jt #1, #loop
+             addr:  db 0
+             x:     db 0
+             y:     db 0
+             t:     db 0
+             dest:  db 0",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn nat_packet() {
+        assert_eq!(
+            Ok(Packet {
+                dest: NAT,
+                x: 9,
+                y: 5
+            }),
+            first_nat_packet(&ring(), 3)
+        );
+    }
+
+    #[test]
+    fn rounds_are_deterministic() {
+        let mut network = Network::new(&ring(), 3);
+
+        let sent = |from, dest, x| Event::Sent {
+            from,
+            packet: Packet { dest, x, y: 5 },
+        };
+
+        // Packets are delivered straight away, so the one sent by 0 is
+        // picked up by 1 and 2 in the same round.
+        assert_eq!(
+            Ok(vec![sent(0, 1, 7), sent(1, 2, 8), sent(2, NAT, 9)]),
+            network.round()
+        );
+        assert_eq!(Ok(vec![]), network.round());
+        assert_eq!(
+            Ok(vec![Event::Wake(Packet {
+                dest: 0,
+                x: 9,
+                y: 5
+            })]),
+            network.round()
+        );
+        assert_eq!(3, network.rounds());
+    }
+
+    #[test]
+    fn repeated_wake() {
+        assert_eq!(Ok(5), first_repeated_wake(&ring(), 3));
+    }
+
+    #[test]
+    fn unknown_address() {
+        assert_eq!(
+            Err(Error::UnknownAddress { from: 1, dest: 2 }),
+            first_nat_packet(&ring(), 2)
+        );
+    }
+
+    #[test]
+    fn stalled() {
+        let program =
+            assemble("loop: in [x]\n jt #1, #loop\n x: db 0").unwrap();
+
+        assert_eq!(Err(Error::Stalled), first_repeated_wake(&program, 2));
+    }
+}
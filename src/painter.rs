@@ -0,0 +1,281 @@
+use std::collections::{HashMap, HashSet};
+
+use snafu::{ResultExt, Snafu};
+
+use crate::grid::{self, Heading, Point, Turn};
+use crate::intcode::{self, Machine, Memory, State};
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Robot program failed: {}", source))]
+    Intcode { source: intcode::Error },
+    #[snafu(display("Robot sent an unknown command {:?}", outputs))]
+    BadCommand { outputs: Vec<i64> },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Cell {
+    Black,
+    White,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Action {
+    Paint(Cell),
+    Turn(Turn),
+    Forward,
+}
+
+/// How a robot program talks about the cell under it and what to do next.
+pub trait Protocol {
+    /// Number of outputs that make up one command.
+    fn arity(&self) -> usize;
+
+    /// The input telling the program what colour it's standing on.
+    fn observe(&self, cell: Cell) -> i64;
+
+    /// Turns a complete command into actions, or `None` if it makes no
+    /// sense.
+    fn command(&self, outputs: &[i64]) -> Option<Vec<Action>>;
+}
+
+/// Colours are 0 for black and 1 for white. Each command is a colour to
+/// paint followed by a turn (0 left, 1 right), after which the robot moves
+/// forward one cell.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PaintAndTurn;
+
+impl Protocol for PaintAndTurn {
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn observe(&self, cell: Cell) -> i64 {
+        match cell {
+            Cell::Black => 0,
+            Cell::White => 1,
+        }
+    }
+
+    fn command(&self, outputs: &[i64]) -> Option<Vec<Action>> {
+        let paint = match outputs[0] {
+            0 => Cell::Black,
+            1 => Cell::White,
+            _ => return None,
+        };
+        let turn = match outputs[1] {
+            0 => Turn::Left,
+            1 => Turn::Right,
+            _ => return None,
+        };
+
+        Some(vec![
+            Action::Paint(paint),
+            Action::Turn(turn),
+            Action::Forward,
+        ])
+    }
+}
+
+/// A robot driven by an intcode program over an infinite black surface.
+#[derive(Debug, Clone)]
+pub struct Robot<P> {
+    machine: Machine,
+    protocol: P,
+    position: Point,
+    heading: Heading,
+    surface: HashMap<Point, Cell>,
+    painted: HashSet<Point>,
+}
+
+impl<P: Protocol> Robot<P> {
+    /// Starts at the origin facing north.
+    pub fn new(program: Memory, protocol: P) -> Robot<P> {
+        Robot {
+            machine: Machine::new(program),
+            protocol,
+            position: Point { x: 0, y: 0 },
+            heading: Heading::North,
+            surface: HashMap::new(),
+            painted: HashSet::new(),
+        }
+    }
+
+    pub fn position(&self) -> Point {
+        self.position
+    }
+
+    pub fn heading(&self) -> Heading {
+        self.heading
+    }
+
+    pub fn surface(&self) -> &HashMap<Point, Cell> {
+        &self.surface
+    }
+
+    pub fn surface_mut(&mut self) -> &mut HashMap<Point, Cell> {
+        &mut self.surface
+    }
+
+    /// Points the robot has painted at least once, whatever the colour.
+    pub fn painted(&self) -> &HashSet<Point> {
+        &self.painted
+    }
+
+    fn act(&mut self, action: Action) {
+        match action {
+            Action::Paint(cell) => {
+                self.surface.insert(self.position, cell);
+                self.painted.insert(self.position);
+            }
+            Action::Turn(turn) => self.heading = self.heading.turn(turn),
+            Action::Forward => self.position = self.heading.step(self.position),
+        }
+    }
+
+    /// Runs the program until it halts.
+    pub fn run(&mut self) -> Result<(), Error> {
+        let mut outputs = vec![];
+
+        loop {
+            match self.machine.run().context(Intcode)? {
+                State::NeedsInput => {
+                    let cell = self
+                        .surface
+                        .get(&self.position)
+                        .copied()
+                        .unwrap_or(Cell::Black);
+                    self.machine.push_input(self.protocol.observe(cell));
+                }
+                State::Output(value) => {
+                    outputs.push(value);
+                    if outputs.len() < self.protocol.arity() {
+                        continue;
+                    }
+
+                    let actions = self.protocol.command(&outputs).ok_or(
+                        Error::BadCommand {
+                            outputs: outputs.clone(),
+                        },
+                    )?;
+                    for action in actions {
+                        self.act(action);
+                    }
+                    outputs.clear();
+                }
+                State::Halted => return Ok(()),
+            }
+        }
+    }
+
+    /// Draws the surface with white cells as `#`.
+    pub fn render(&self) -> String {
+        grid::render(&self.surface, |cell| match cell {
+            Some(Cell::White) => '#',
+            _ => ' ',
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::load_program;
+
+    /// A program that reads an input before each of `commands` and
+    /// otherwise ignores it.
+    fn scripted(commands: &[[i64; 2]]) -> Memory {
+        let mut program = vec![];
+        for [paint, turn] in commands {
+            program.extend(vec![3, 0, 104, *paint, 104, *turn]);
+        }
+        program.push(99);
+
+        program.into()
+    }
+
+    fn example() -> Robot<PaintAndTurn> {
+        let commands = [[1, 0], [0, 0], [1, 0], [1, 0], [0, 1], [1, 0], [1, 0]];
+
+        Robot::new(scripted(&commands), PaintAndTurn)
+    }
+
+    #[test]
+    fn painting() {
+        let mut robot = example();
+        robot.run().unwrap();
+
+        assert_eq!(6, robot.painted().len());
+        assert_eq!(Point { x: 0, y: -1 }, robot.position());
+        assert_eq!(Heading::West, robot.heading());
+        assert_eq!("  #\n  #\n## \n", robot.render());
+    }
+
+    #[test]
+    fn observes_the_surface() {
+        // Twice over: paints the colour it sees, then turns right.
+        let program =
+            load_program("3,13,4,13,104,1,3,13,4,13,104,1,99,0").unwrap();
+        let mut robot = Robot::new(program, PaintAndTurn);
+        robot
+            .surface_mut()
+            .insert(Point { x: 1, y: 0 }, Cell::White);
+        robot.run().unwrap();
+
+        assert_eq!(2, robot.painted().len());
+        assert_eq!(
+            Some(&Cell::Black),
+            robot.surface().get(&Point { x: 0, y: 0 })
+        );
+        assert_eq!(
+            Some(&Cell::White),
+            robot.surface().get(&Point { x: 1, y: 0 })
+        );
+        assert_eq!(Point { x: 1, y: 1 }, robot.position());
+    }
+
+    #[test]
+    fn bad_command() {
+        let mut robot = Robot::new(scripted(&[[2, 0]]), PaintAndTurn);
+
+        assert_eq!(
+            Err(Error::BadCommand {
+                outputs: vec![2, 0]
+            }),
+            robot.run()
+        );
+    }
+
+    /// Outputs are a number of cells to move forward, turning left first.
+    struct Crawl;
+
+    impl Protocol for Crawl {
+        fn arity(&self) -> usize {
+            1
+        }
+
+        fn observe(&self, _: Cell) -> i64 {
+            0
+        }
+
+        fn command(&self, outputs: &[i64]) -> Option<Vec<Action>> {
+            let mut actions = vec![Action::Turn(Turn::Left)];
+            for _ in 0..outputs[0] {
+                actions.push(Action::Paint(Cell::White));
+                actions.push(Action::Forward);
+            }
+
+            Some(actions)
+        }
+    }
+
+    #[test]
+    fn custom_protocol() {
+        let program = load_program("104,2,104,1,99").unwrap();
+        let mut robot = Robot::new(program, Crawl);
+        robot.run().unwrap();
+
+        assert_eq!(Point { x: -2, y: 1 }, robot.position());
+        assert_eq!("###\n", robot.render());
+    }
+}
@@ -0,0 +1,422 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use snafu::{ResultExt, Snafu};
+
+use crate::grid::{self, Heading, Point, Turn};
+use crate::intcode::{self, ascii::Ascii, Memory};
+
+/// Longest line the robot will accept for a routine or function.
+pub const MAX_LINE: usize = 20;
+
+/// Number of movement functions, A to C.
+const FUNCTIONS: usize = 3;
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Vacuum robot program failed: {}", source))]
+    Intcode { source: intcode::Error },
+    #[snafu(display("No robot in the camera image"))]
+    NoRobot,
+    #[snafu(display(
+        "No routines of at most {} characters cover the path",
+        limit
+    ))]
+    NoRoutines { limit: usize },
+    #[snafu(display("Robot didn't report any dust, it said:\n{}", text))]
+    NoDust { text: String },
+}
+
+/// One turn followed by some steps forward. Only the first move can go
+/// straight ahead without turning.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Move {
+    pub turn: Option<Turn>,
+    pub steps: usize,
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.turn {
+            Some(Turn::Left) => write!(f, "L,{}", self.steps),
+            Some(Turn::Right) => write!(f, "R,{}", self.steps),
+            None => write!(f, "{}", self.steps),
+        }
+    }
+}
+
+/// Moves as the robot takes them, e.g. `R,8,L,10`.
+pub fn format_moves(moves: &[Move]) -> String {
+    let moves: Vec<String> = moves.iter().map(Move::to_string).collect();
+    moves.join(",")
+}
+
+/// What the camera sees: the scaffold and the robot on it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Camera {
+    scaffold: HashSet<Point>,
+    robot: Option<(Point, Heading)>,
+}
+
+impl Camera {
+    /// Reads the camera image. A robot that has fallen off the scaffold
+    /// (`X`) is ignored.
+    pub fn parse(text: &str) -> Camera {
+        let mut camera = Camera::default();
+
+        for (y, line) in text.lines().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let point = Point {
+                    x: x as i32,
+                    y: y as i32,
+                };
+                let heading = match c {
+                    '#' => None,
+                    '^' => Some(Heading::North),
+                    '>' => Some(Heading::East),
+                    'v' => Some(Heading::South),
+                    '<' => Some(Heading::West),
+                    _ => continue,
+                };
+
+                camera.scaffold.insert(point);
+                if let Some(heading) = heading {
+                    camera.robot = Some((point, heading));
+                }
+            }
+        }
+
+        camera
+    }
+
+    pub fn scaffold(&self) -> &HashSet<Point> {
+        &self.scaffold
+    }
+
+    pub fn robot(&self) -> Option<(Point, Heading)> {
+        self.robot
+    }
+
+    /// Scaffold points with scaffold on all four sides.
+    pub fn intersections(&self) -> Vec<Point> {
+        let mut points: Vec<Point> = self
+            .scaffold
+            .iter()
+            .filter(|&&p| {
+                grid::neighbours(p).all(|n| self.scaffold.contains(&n))
+            })
+            .copied()
+            .collect();
+        points.sort_by_key(|p| (p.y, p.x));
+
+        points
+    }
+
+    /// Sum of x times y over the intersections.
+    pub fn alignment(&self) -> i32 {
+        self.intersections().iter().map(|p| p.x * p.y).sum()
+    }
+
+    /// The moves that take the robot to the end of the scaffold, going
+    /// straight on at every intersection.
+    pub fn path(&self) -> Result<Vec<Move>, Error> {
+        let (mut at, mut heading) = self.robot.ok_or(Error::NoRobot)?;
+        let mut moves = vec![];
+
+        let forward = |at: &mut Point, heading: Heading| {
+            let mut steps = 0;
+            while self.scaffold.contains(&heading.step(*at)) {
+                *at = heading.step(*at);
+                steps += 1;
+            }
+            steps
+        };
+
+        let steps = forward(&mut at, heading);
+        if steps > 0 {
+            moves.push(Move { turn: None, steps });
+        }
+
+        loop {
+            let turn = [Turn::Left, Turn::Right]
+                .iter()
+                .copied()
+                .find(|&t| self.scaffold.contains(&heading.turn(t).step(at)));
+            let turn = match turn {
+                Some(turn) => turn,
+                None => return Ok(moves),
+            };
+
+            heading = heading.turn(turn);
+            let steps = forward(&mut at, heading);
+            moves.push(Move {
+                turn: Some(turn),
+                steps,
+            });
+        }
+    }
+}
+
+/// A main routine calling movement functions A, B and C.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routines {
+    /// Indexes into `functions`.
+    pub main: Vec<usize>,
+    pub functions: Vec<Vec<Move>>,
+}
+
+impl Routines {
+    /// The main routine followed by each function, as the robot wants them.
+    /// Unused functions are sent as empty lines.
+    pub fn lines(&self) -> Vec<String> {
+        let main: Vec<String> = self
+            .main
+            .iter()
+            .map(|&i| char::from(b'A' + i as u8).to_string())
+            .collect();
+
+        let mut lines = vec![main.join(",")];
+        for i in 0..FUNCTIONS {
+            lines.push(
+                self.functions
+                    .get(i)
+                    .map_or(String::new(), |f| format_moves(f)),
+            );
+        }
+
+        lines
+    }
+
+    /// The full path the routines make the robot take.
+    pub fn expand(&self) -> Vec<Move> {
+        self.main
+            .iter()
+            .flat_map(|&i| self.functions[i].iter().copied())
+            .collect()
+    }
+}
+
+/// Splits `path` into a main routine and up to three functions, each no
+/// longer than `limit` characters.
+pub fn compress(path: &[Move], limit: usize) -> Result<Routines, Error> {
+    let mut functions = vec![];
+    let mut main = vec![];
+
+    if !search(path, 0, limit, &mut functions, &mut main) {
+        return NoRoutines { limit }.fail();
+    }
+
+    Ok(Routines {
+        main,
+        functions: functions.iter().map(|f| f.to_vec()).collect(),
+    })
+}
+
+/// Covers `path[at..]` with calls to existing functions, or a new one
+/// starting at `at`, longest first.
+fn search<'a>(
+    path: &'a [Move],
+    at: usize,
+    limit: usize,
+    functions: &mut Vec<&'a [Move]>,
+    main: &mut Vec<usize>,
+) -> bool {
+    if at == path.len() {
+        return true;
+    }
+    // Each call takes a letter and a comma.
+    if (main.len() + 1) * 2 - 1 > limit {
+        return false;
+    }
+
+    for i in 0..functions.len() {
+        let function = functions[i];
+        if path[at..].starts_with(function) {
+            main.push(i);
+            if search(path, at + function.len(), limit, functions, main) {
+                return true;
+            }
+            main.pop();
+        }
+    }
+
+    if functions.len() == FUNCTIONS {
+        return false;
+    }
+
+    let longest = (1..=path.len() - at)
+        .take_while(|&len| format_moves(&path[at..at + len]).len() <= limit)
+        .last()
+        .unwrap_or(0);
+
+    for len in (1..=longest).rev() {
+        functions.push(&path[at..at + len]);
+        main.push(functions.len() - 1);
+        if search(path, at + len, limit, functions, main) {
+            return true;
+        }
+        main.pop();
+        functions.pop();
+    }
+
+    false
+}
+
+/// Runs the program once to get a camera image.
+pub fn view(program: Memory) -> Result<Camera, Error> {
+    let reply = Ascii::new(program).run().context(Intcode)?;
+    Ok(Camera::parse(&reply.text))
+}
+
+/// Wakes the robot up (address 0 set to 2), sends it `routines` with the
+/// video feed off, and returns the dust it collected.
+pub fn wake(mut program: Memory, routines: &Routines) -> Result<i64, Error> {
+    program[0] = 2;
+    let mut ascii = Ascii::new(program);
+
+    let mut lines = routines.lines();
+    lines.push("n".to_string());
+    let reply = ascii
+        .run_script(lines.iter().map(String::as_str))
+        .context(Intcode)?;
+
+    reply
+        .numbers
+        .last()
+        .copied()
+        .ok_or(Error::NoDust { text: reply.text })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    const CROSSES: &str = "\
+..#..........
+..#..........
+#######...###
+#.#...#...#.#
+#############
+..#...#...#..
+..#####...^..
+";
+
+    const LOOPS: &str = "\
+#######...#####
+#.....#...#...#
+#.....#...#...#
+......#...#...#
+......#...###.#
+......#.....#.#
+^########...#.#
+......#.#...#.#
+......#########
+........#...#..
+....#########..
+....#...#......
+....#...#......
+....#...#......
+....#####......
+";
+
+    #[test]
+    fn alignment() {
+        let camera = Camera::parse(CROSSES);
+
+        assert_eq!(4, camera.intersections().len());
+        assert_eq!(Point { x: 2, y: 2 }, camera.intersections()[0]);
+        assert_eq!(76, camera.alignment());
+        assert_eq!(
+            Some((Point { x: 10, y: 6 }, Heading::North)),
+            camera.robot()
+        );
+    }
+
+    #[test]
+    fn path() {
+        let path = Camera::parse(LOOPS).path().unwrap();
+
+        assert_eq!(
+            "R,8,R,8,R,4,R,4,R,8,L,6,L,2,R,4,R,4,R,8,R,8,R,8,L,6,L,2",
+            format_moves(&path)
+        );
+    }
+
+    #[test]
+    fn straight_start() {
+        let camera = Camera::parse("#####\n#....\n^....\n");
+
+        assert_eq!("2,R,4", format_moves(&camera.path().unwrap()));
+    }
+
+    #[test]
+    fn no_robot() {
+        assert_eq!(Err(Error::NoRobot), Camera::parse("###\n").path());
+    }
+
+    #[test]
+    fn compressing() {
+        let path = Camera::parse(LOOPS).path().unwrap();
+        let routines = compress(&path, MAX_LINE).unwrap();
+
+        assert_eq!(path, routines.expand());
+        assert!(routines.lines().iter().all(|l| l.len() <= MAX_LINE));
+        assert_eq!(Err(Error::NoRoutines { limit: 3 }), compress(&path, 3));
+    }
+
+    #[test]
+    fn unused_functions() {
+        let right = Move {
+            turn: Some(Turn::Right),
+            steps: 8,
+        };
+        let left = Move {
+            turn: Some(Turn::Left),
+            steps: 4,
+        };
+        let path = [right, right, right, right, left, left, left, left];
+        let routines = compress(&path, 8).unwrap();
+
+        assert_eq!(path.to_vec(), routines.expand());
+        assert_eq!(2, routines.functions.len());
+        assert_eq!(vec!["A,A,B,B", "R,8,R,8", "L,4,L,4", ""], routines.lines());
+    }
+
+    #[test]
+    fn waking() {
+        // Address 0 holds an add which becomes a multiply when woken, so
+        // `mode` ends up 9 instead of 6. Reports 1000 times the mode plus
+        // the number of characters read over five lines.
+        let program = assemble(
+            "      add [three], [three], [mode]
+             loop: in [c]
+                   add [n], #1, [n]
+                   eq [c], #10, [t]
+                   add [lines], [t], [lines]
+                   eq [lines], #5, [t]
+                   jf [t], #loop
+                   mul [mode], #1000, [t]
+                   add [t], [n], [t]
+                   out [t]
+                   halt
+             three: db 3
+             mode:  db 0
+             c:     db 0
+             n:     db 0
+             t:     db 0
+             lines: db 0",
+        )
+        .unwrap();
+        let routines = Routines {
+            main: vec![0, 0],
+            functions: vec![vec![Move {
+                turn: Some(Turn::Right),
+                steps: 8,
+            }]],
+        };
+
+        // "A,A", "R,8", "", "" and "n", with their newlines.
+        assert_eq!(Ok(9000 + 4 + 4 + 1 + 1 + 2), wake(program, &routines));
+    }
+}
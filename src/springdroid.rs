@@ -0,0 +1,627 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use snafu::{ResultExt, Snafu};
+
+use crate::intcode::{self, ascii::Ascii, Memory};
+
+pub mod synth;
+
+/// The most instructions the springdroid will accept.
+pub const MAX_INSTRUCTIONS: usize = 15;
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Column {}: {}", column, message))]
+    Syntax { column: usize, message: String },
+    #[snafu(display("Sensor {} isn't available when walking", sensor))]
+    OutOfRange { sensor: char },
+    #[snafu(display(
+        "Expression needs {} instructions, more than the limit of {}",
+        length,
+        MAX_INSTRUCTIONS
+    ))]
+    TooLong { length: usize },
+    #[snafu(display("Expression needs more than two registers"))]
+    TooComplex,
+    #[snafu(display("Springdroid program failed: {}", source))]
+    Intcode { source: intcode::Error },
+    #[snafu(display("Springdroid fell but didn't show where:\n{}", text))]
+    NoHull { text: String },
+    #[snafu(display("No jumping rule gets across every hull seen"))]
+    Unsolvable,
+    #[snafu(display("Still falling after {} attempts", attempts))]
+    GaveUp { attempts: usize },
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    /// Sensors A-D.
+    Walk,
+    /// Sensors A-I.
+    Run,
+}
+
+impl Mode {
+    pub fn sensors(self) -> u8 {
+        match self {
+            Mode::Walk => 4,
+            Mode::Run => 9,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Register {
+    /// Whether there's ground n + 1 tiles ahead.
+    Sensor(u8),
+    T,
+    J,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::Sensor(n) => write!(f, "{}", (b'A' + n) as char),
+            Register::T => write!(f, "T"),
+            Register::J => write!(f, "J"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Op {
+    And,
+    Or,
+    Not,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Instruction {
+    pub op: Op,
+    pub x: Register,
+    pub y: Register,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self.op {
+            Op::And => "AND",
+            Op::Or => "OR",
+            Op::Not => "NOT",
+        };
+
+        write!(f, "{} {} {}", op, self.x, self.y)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Script {
+    pub instructions: Vec<Instruction>,
+    pub mode: Mode,
+}
+
+impl Script {
+    /// Runs the script against a set of sensor readings, where bit n is set
+    /// if sensor n sees ground, and returns whether the droid jumps.
+    pub fn jumps(&self, sensors: u16) -> bool {
+        let (mut t, mut j) = (false, false);
+
+        for i in &self.instructions {
+            let x = match i.x {
+                Register::Sensor(n) => sensors & (1 << n) != 0,
+                Register::T => t,
+                Register::J => j,
+            };
+            let y = if i.y == Register::T { &mut t } else { &mut j };
+
+            *y = match i.op {
+                Op::And => x && *y,
+                Op::Or => x || *y,
+                Op::Not => !x,
+            };
+        }
+
+        j
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for i in &self.instructions {
+            writeln!(f, "{}", i)?;
+        }
+
+        match self.mode {
+            Mode::Walk => writeln!(f, "WALK"),
+            Mode::Run => writeln!(f, "RUN"),
+        }
+    }
+}
+
+/// A boolean expression over the sensors.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Sensor(u8),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+impl Expr {
+    /// Parses expressions like `J = !A | (!C & D & H)`. The `J =` is
+    /// optional, `!` binds tightest and `&` binds tighter than `|`.
+    pub fn parse(text: &str) -> Result<Expr, Error> {
+        let mut parser = Parser {
+            chars: text.char_indices().peekable(),
+            len: text.len(),
+        };
+
+        parser.skip_target();
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((column, c)) => Syntax {
+                column,
+                message: format!("unexpected {:?}", c),
+            }
+            .fail(),
+        }
+    }
+
+    pub fn eval(&self, sensors: u16) -> bool {
+        match self {
+            Expr::Sensor(n) => sensors & (1 << n) != 0,
+            Expr::Not(e) => !e.eval(sensors),
+            Expr::And(es) => es.iter().all(|e| e.eval(sensors)),
+            Expr::Or(es) => es.iter().any(|e| e.eval(sensors)),
+        }
+    }
+
+    fn highest_sensor(&self) -> Option<u8> {
+        match self {
+            Expr::Sensor(n) => Some(*n),
+            Expr::Not(e) => e.highest_sensor(),
+            Expr::And(es) | Expr::Or(es) => {
+                es.iter().filter_map(Expr::highest_sensor).max()
+            }
+        }
+    }
+
+    /// Pushes negations down to the sensors and flattens nested ands and
+    /// ors.
+    fn normalise(self, negate: bool) -> Expr {
+        let flatten = |es: Vec<Expr>, and: bool| {
+            let mut flat = vec![];
+            for e in es {
+                match (e.normalise(negate), and) {
+                    (Expr::And(inner), true) | (Expr::Or(inner), false) => {
+                        flat.extend(inner)
+                    }
+                    (e, _) => flat.push(e),
+                }
+            }
+
+            if flat.len() == 1 {
+                flat.pop().unwrap()
+            } else if and {
+                Expr::And(flat)
+            } else {
+                Expr::Or(flat)
+            }
+        };
+
+        match self {
+            Expr::Sensor(_) if negate => Expr::Not(Box::new(self)),
+            Expr::Sensor(_) => self,
+            Expr::Not(e) => e.normalise(!negate),
+            Expr::And(es) => flatten(es, !negate),
+            Expr::Or(es) => flatten(es, negate),
+        }
+    }
+
+    fn literal(&self) -> Option<(u8, bool)> {
+        match self {
+            Expr::Sensor(n) => Some((*n, true)),
+            Expr::Not(e) => match **e {
+                Expr::Sensor(n) => Some((n, false)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter, es: &[Expr], op: &str| {
+            for (n, e) in es.iter().enumerate() {
+                if n > 0 {
+                    write!(f, " {} ", op)?;
+                }
+                match e {
+                    Expr::And(_) | Expr::Or(_) => write!(f, "({})", e)?,
+                    _ => write!(f, "{}", e)?,
+                }
+            }
+            Ok(())
+        };
+
+        match self {
+            Expr::Sensor(n) => write!(f, "{}", Register::Sensor(*n)),
+            Expr::Not(e) => match **e {
+                Expr::Sensor(_) | Expr::Not(_) => write!(f, "!{}", e),
+                _ => write!(f, "!({})", e),
+            },
+            Expr::And(es) => join(f, es, "&"),
+            Expr::Or(es) => join(f, es, "|"),
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Option<(usize, char)> {
+        while let Some((_, c)) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+
+        self.chars.peek().map(|&(i, c)| (i + 1, c))
+    }
+
+    fn skip_target(&mut self) {
+        let rest: String = self
+            .chars
+            .clone()
+            .map(|(_, c)| c)
+            .filter(|c| !c.is_whitespace())
+            .take(2)
+            .collect();
+
+        if rest == "J=" {
+            for (_, c) in self.chars.by_ref() {
+                if c == '=' {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut terms = vec![self.and()?];
+        while let Some((_, '|')) = self.peek() {
+            self.chars.next();
+            terms.push(self.and()?);
+        }
+
+        Ok(if terms.len() == 1 {
+            terms.pop().unwrap()
+        } else {
+            Expr::Or(terms)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut factors = vec![self.unary()?];
+        while let Some((_, '&')) = self.peek() {
+            self.chars.next();
+            factors.push(self.unary()?);
+        }
+
+        Ok(if factors.len() == 1 {
+            factors.pop().unwrap()
+        } else {
+            Expr::And(factors)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        let (column, c) = match self.peek() {
+            Some(next) => next,
+            None => {
+                return Syntax {
+                    column: self.len + 1,
+                    message: "unexpected end of expression",
+                }
+                .fail()
+            }
+        };
+        self.chars.next();
+
+        match c {
+            '!' => Ok(Expr::Not(Box::new(self.unary()?))),
+            '(' => {
+                let e = self.or()?;
+                match self.peek() {
+                    Some((_, ')')) => {
+                        self.chars.next();
+                        Ok(e)
+                    }
+                    _ => Syntax {
+                        column,
+                        message: "unclosed parenthesis",
+                    }
+                    .fail(),
+                }
+            }
+            'A'..='I' => Ok(Expr::Sensor(c as u8 - b'A')),
+            _ => Syntax {
+                column,
+                message: format!("expected a sensor, found {:?}", c),
+            }
+            .fail(),
+        }
+    }
+}
+
+/// Emits instructions for a normalised expression using only T and J.
+struct Codegen {
+    code: Vec<Instruction>,
+    /// Whether T and J still hold their initial false.
+    clear: [bool; 2],
+}
+
+impl Codegen {
+    fn emit(&mut self, op: Op, x: Register, y: Register) {
+        self.clear[(y == Register::J) as usize] = false;
+        self.code.push(Instruction { op, x, y });
+    }
+
+    fn is_clear(&self, register: Register) -> bool {
+        self.clear[(register == Register::J) as usize]
+    }
+
+    /// Computes `expr` into `dest`. `scratch` is the other register if it's
+    /// free to clobber.
+    fn gen(
+        &mut self,
+        expr: &Expr,
+        dest: Register,
+        scratch: Option<Register>,
+    ) -> Result<(), Error> {
+        use Register::Sensor;
+
+        if let Some((n, positive)) = expr.literal() {
+            if !positive {
+                self.emit(Op::Not, Sensor(n), dest);
+            } else if self.is_clear(dest) {
+                self.emit(Op::Or, Sensor(n), dest);
+            } else {
+                self.emit(Op::Not, Sensor(n), dest);
+                self.emit(Op::Not, dest, dest);
+            }
+            return Ok(());
+        }
+
+        let (operands, op) = match expr {
+            Expr::And(es) => (es, Op::And),
+            Expr::Or(es) => (es, Op::Or),
+            _ => return TooComplex.fail(),
+        };
+
+        // Anything that isn't a literal needs a register of its own, which
+        // is easiest to find before dest is in use. Among literals, a
+        // negated one is cheapest first and dearest later.
+        let mut order: Vec<&Expr> = operands.iter().collect();
+        order.sort_by_key(|e| match e.literal() {
+            None => 0,
+            Some((_, false)) => 1,
+            Some((_, true)) => 2,
+        });
+
+        self.gen(order[0], dest, scratch)?;
+
+        for e in &order[1..] {
+            match (e.literal(), scratch) {
+                (Some((n, true)), _) => self.emit(op, Sensor(n), dest),
+                (Some((n, false)), Some(s)) => {
+                    self.emit(Op::Not, Sensor(n), s);
+                    self.emit(op, s, dest);
+                }
+                (Some((n, false)), None) => {
+                    // x & !n == !(!x | n), and x | !n == !(!x & n)
+                    let flipped = if op == Op::And { Op::Or } else { Op::And };
+                    self.emit(Op::Not, dest, dest);
+                    self.emit(flipped, Sensor(n), dest);
+                    self.emit(Op::Not, dest, dest);
+                }
+                (None, Some(s)) => {
+                    self.gen(e, s, None)?;
+                    self.emit(op, s, dest);
+                }
+                (None, None) => return TooComplex.fail(),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Compiles `expr` to a script that jumps exactly when it's true.
+pub fn compile(expr: &Expr, mode: Mode) -> Result<Script, Error> {
+    if let Some(n) = expr.highest_sensor().filter(|&n| n >= mode.sensors()) {
+        return OutOfRange {
+            sensor: (b'A' + n) as char,
+        }
+        .fail();
+    }
+
+    let mut codegen = Codegen {
+        code: vec![],
+        clear: [true, true],
+    };
+    codegen.gen(
+        &expr.clone().normalise(false),
+        Register::J,
+        Some(Register::T),
+    )?;
+
+    if codegen.code.len() > MAX_INSTRUCTIONS {
+        return TooLong {
+            length: codegen.code.len(),
+        }
+        .fail();
+    }
+
+    Ok(Script {
+        instructions: codegen.code,
+        mode,
+    })
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Outcome {
+    /// Made it across, reporting this much hull damage.
+    Survived(i64),
+    /// Fell in a hole on this stretch of hull, drawn as `#` for ground and
+    /// `.` for holes, starting where the droid stood.
+    Fell(String),
+}
+
+/// Finds the hull in the springdroid's last moments: the first line made
+/// only of ground and holes.
+fn fallen_hull(text: &str) -> Option<String> {
+    text.lines()
+        .map(str::trim_end)
+        .find(|line| {
+            line.contains('#') && line.chars().all(|c| c == '#' || c == '.')
+        })
+        .map(String::from)
+}
+
+/// Feeds `script` to a springdroid program and reports how it went.
+pub fn run(program: &Memory, script: &Script) -> Result<Outcome, Error> {
+    let mut ascii = Ascii::new(program.clone());
+    let reply = ascii
+        .run_script(script.to_string().lines())
+        .context(Intcode)?;
+
+    if let Some(&damage) = reply.numbers.first() {
+        return Ok(Outcome::Survived(damage));
+    }
+
+    fallen_hull(&reply.text)
+        .map(Outcome::Fell)
+        .ok_or(Error::NoHull { text: reply.text })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_equivalent(expr: &Expr, script: &Script) {
+        for sensors in 0..1 << script.mode.sensors() {
+            assert_eq!(
+                expr.eval(sensors),
+                script.jumps(sensors),
+                "{} with sensors {:09b}",
+                expr,
+                sensors
+            );
+        }
+    }
+
+    #[test]
+    fn parsing() {
+        let expr = Expr::parse("J = !A | (!C & D & H)").unwrap();
+
+        assert_eq!(
+            Expr::Or(vec![
+                Expr::Not(Box::new(Expr::Sensor(0))),
+                Expr::And(vec![
+                    Expr::Not(Box::new(Expr::Sensor(2))),
+                    Expr::Sensor(3),
+                    Expr::Sensor(7),
+                ]),
+            ]),
+            expr
+        );
+        assert_eq!("!A | (!C & D & H)", expr.to_string());
+        assert_eq!(Ok(expr), Expr::parse("!A|(!C&D&H)"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            Err(Error::Syntax {
+                column: 5,
+                message: "expected a sensor, found 'T'".to_string()
+            }),
+            Expr::parse("A & T")
+        );
+        assert!(Expr::parse("(A | B").is_err());
+        assert!(Expr::parse("A B").is_err());
+        assert!(Expr::parse("").is_err());
+    }
+
+    #[test]
+    fn compiling() {
+        let expr = Expr::parse("!A | (!C & D & H)").unwrap();
+        let script = compile(&expr, Mode::Run).unwrap();
+
+        assert_eq!(
+            "NOT C J\nAND D J\nAND H J\nNOT A T\nOR T J\nRUN\n",
+            script.to_string()
+        );
+        assert_equivalent(&expr, &script);
+    }
+
+    #[test]
+    fn compiles_equivalent_scripts() {
+        for text in &[
+            "D",
+            "!D",
+            "!(A & B & C) & D",
+            "(!A | !B | !C) & D & (E | H)",
+            "!(!A | B) | (C & !(D | !E))",
+            "(A | B) & (C | !D) & !E",
+            "((A & B) | C) & D",
+        ] {
+            let expr = Expr::parse(text).unwrap();
+            assert_equivalent(&expr, &compile(&expr, Mode::Run).unwrap());
+        }
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(
+            Err(Error::OutOfRange { sensor: 'E' }),
+            compile(&Expr::parse("A & E").unwrap(), Mode::Walk)
+        );
+        assert_eq!(
+            Err(Error::TooComplex),
+            compile(
+                &Expr::parse("(A & B | C & D) & (E & F | G & H)").unwrap(),
+                Mode::Run
+            )
+        );
+        assert!(matches!(
+            compile(
+                &Expr::parse("!A & !B & !C & !D & !E & !F & !G & !H & !I")
+                    .unwrap(),
+                Mode::Run
+            ),
+            Err(Error::TooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn finding_the_hull() {
+        let text = "Input instructions:\n\nWalking...\n\n\
+                    Didn't make it across:\n\n\
+                    .................\n\
+                    .................\n\
+                    @................\n\
+                    #####.#..########\n\n";
+
+        assert_eq!(Some("#####.#..########".to_string()), fallen_hull(text));
+    }
+}
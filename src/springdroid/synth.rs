@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use super::{compile, Error, Expr, GaveUp, Mode, Outcome, Script, Unsolvable};
+
+/// How far a jump carries the droid.
+const JUMP: usize = 4;
+
+/// The most literals in a term the learner will try before falling back to
+/// matching a single set of readings exactly.
+const MAX_TERM: u32 = 3;
+
+/// What the sensors read standing at `at`: bit n is set if there's ground
+/// n + 1 tiles ahead. Everything past the end of the hull is ground.
+pub fn sensors(hull: &[u8], at: usize, mode: Mode) -> u16 {
+    (0..mode.sensors()).fold(0, |bits, n| match hull.get(at + 1 + n as usize) {
+        Some(b'.') => bits,
+        _ => bits | 1 << n,
+    })
+}
+
+/// Whether a droid deciding with `jumps` gets to the end of `hull`.
+pub fn survives(hull: &str, mode: Mode, jumps: impl Fn(u16) -> bool) -> bool {
+    let hull = hull.as_bytes();
+    let mut at = 0;
+
+    while at < hull.len() {
+        if hull[at] == b'.' {
+            return false;
+        }
+        at += if jumps(sensors(hull, at, mode)) {
+            JUMP
+        } else {
+            1
+        };
+    }
+
+    true
+}
+
+/// Searches for jump decisions that get the droid across every hull,
+/// keyed by sensor readings, without ever deciding the same readings two
+/// ways.
+fn decide(
+    hulls: &[&[u8]],
+    at: usize,
+    mode: Mode,
+    decisions: &mut HashMap<u16, bool>,
+) -> bool {
+    let hull = match hulls.first() {
+        Some(hull) => hull,
+        None => return true,
+    };
+
+    if at >= hull.len() {
+        return decide(&hulls[1..], 0, mode, decisions);
+    }
+    if hull[at] == b'.' {
+        return false;
+    }
+
+    let readings = sensors(hull, at, mode);
+    if let Some(&jump) = decisions.get(&readings) {
+        let next = if jump { at + JUMP } else { at + 1 };
+        return decide(hulls, next, mode, decisions);
+    }
+
+    for &jump in &[false, true] {
+        decisions.insert(readings, jump);
+        let next = if jump { at + JUMP } else { at + 1 };
+        if decide(hulls, next, mode, decisions) {
+            return true;
+        }
+    }
+    decisions.remove(&readings);
+
+    false
+}
+
+/// A conjunction of sensor literals: readings match when they agree with
+/// `value` on every bit in `mask`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Term {
+    mask: u16,
+    value: u16,
+}
+
+impl Term {
+    fn matches(self, readings: u16) -> bool {
+        readings & self.mask == self.value
+    }
+
+    fn to_expr(self) -> Expr {
+        let literals: Vec<Expr> = (0..16)
+            .filter(|n| self.mask & 1 << n != 0)
+            .map(|n| {
+                let sensor = Expr::Sensor(n);
+                if self.value & 1 << n != 0 {
+                    sensor
+                } else {
+                    Expr::Not(Box::new(sensor))
+                }
+            })
+            .collect();
+
+        match literals.len() {
+            1 => literals.into_iter().next().unwrap(),
+            _ => Expr::And(literals),
+        }
+    }
+}
+
+/// Every term with up to `MAX_TERM` literals, smallest first.
+fn terms(mode: Mode) -> Vec<Term> {
+    let all = (1u16 << mode.sensors()) - 1;
+
+    let mut terms: Vec<Term> = (1..=all)
+        .filter(|mask| mask.count_ones() <= MAX_TERM)
+        .flat_map(|mask| {
+            (0..=mask)
+                .filter(move |value| value & !mask == 0)
+                .map(move |value| Term { mask, value })
+        })
+        .collect();
+    terms.sort_by_key(|t| t.mask.count_ones());
+
+    terms
+}
+
+/// Greedily builds a sum of products that's true for every reading that
+/// has to jump and false for every one that mustn't. Readings that were
+/// never seen are free to go either way. Returns `None` if nothing ever
+/// has to jump.
+fn learn(decisions: &HashMap<u16, bool>, mode: Mode) -> Option<Expr> {
+    let mut positives: Vec<u16> = decisions
+        .iter()
+        .filter(|(_, &jump)| jump)
+        .map(|(&r, _)| r)
+        .collect();
+    let negatives: Vec<u16> = decisions
+        .iter()
+        .filter(|(_, &jump)| !jump)
+        .map(|(&r, _)| r)
+        .collect();
+    positives.sort_unstable();
+
+    let candidates: Vec<Term> = terms(mode)
+        .into_iter()
+        .filter(|t| !negatives.iter().any(|&r| t.matches(r)))
+        .collect();
+
+    let mut chosen = vec![];
+    while !positives.is_empty() {
+        let best = candidates
+            .iter()
+            .copied()
+            .max_by_key(|t| {
+                let covered = positives.iter().filter(|&&r| t.matches(r));
+                // Ties go to the smallest term, which comes first.
+                (covered.count(), std::cmp::Reverse(t.mask.count_ones()))
+            })
+            .filter(|t| positives.iter().any(|&r| t.matches(r)))
+            .unwrap_or(Term {
+                mask: (1 << mode.sensors()) - 1,
+                value: positives[0],
+            });
+
+        positives.retain(|&r| !best.matches(r));
+        chosen.push(best.to_expr());
+    }
+
+    match chosen.len() {
+        0 => None,
+        1 => chosen.pop(),
+        _ => Some(Expr::Or(chosen)),
+    }
+}
+
+/// Derives a script from the hulls the droid has fallen on so far, or
+/// `Unsolvable` if no rule gets across all of them.
+pub fn script_for(hulls: &[String], mode: Mode) -> Result<Script, Error> {
+    let hulls: Vec<&[u8]> = hulls.iter().map(|h| h.as_bytes()).collect();
+    let mut decisions = HashMap::new();
+
+    if !decide(&hulls, 0, mode, &mut decisions) {
+        return Unsolvable.fail();
+    }
+
+    match learn(&decisions, mode) {
+        Some(expr) => compile(&expr, mode),
+        None => Ok(Script {
+            instructions: vec![],
+            mode,
+        }),
+    }
+}
+
+/// Repeatedly tries a script, learning from each hull the droid falls on,
+/// until it makes it across. `attempt` runs a script and reports how it
+/// went, usually with `springdroid::run`.
+pub fn synthesise(
+    mode: Mode,
+    max_attempts: usize,
+    mut attempt: impl FnMut(&Script) -> Result<Outcome, Error>,
+) -> Result<(Script, i64), Error> {
+    let mut hulls = vec![];
+
+    for _ in 0..max_attempts {
+        let script = script_for(&hulls, mode)?;
+
+        match attempt(&script)? {
+            Outcome::Survived(damage) => return Ok((script, damage)),
+            Outcome::Fell(hull) => {
+                if hulls.contains(&hull) {
+                    return Unsolvable.fail();
+                }
+                hulls.push(hull);
+            }
+        }
+    }
+
+    GaveUp {
+        attempts: max_attempts,
+    }
+    .fail()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tries a script on each hull in turn, like the real springdroid does
+    /// with its built-in test cases.
+    fn oracle<'a>(
+        hulls: &'a [&'a str],
+        mode: Mode,
+    ) -> impl FnMut(&Script) -> Result<Outcome, Error> + 'a {
+        move |script| {
+            for hull in hulls {
+                if !survives(hull, mode, |r| script.jumps(r)) {
+                    return Ok(Outcome::Fell(hull.to_string()));
+                }
+            }
+            Ok(Outcome::Survived(42))
+        }
+    }
+
+    #[test]
+    fn reading_sensors() {
+        let hull = b"#####.#..###";
+
+        assert_eq!(0b1111, sensors(hull, 0, Mode::Walk));
+        assert_eq!(0b0101, sensors(hull, 3, Mode::Walk));
+        assert_eq!(0b1_1110_0101, sensors(hull, 3, Mode::Run));
+    }
+
+    #[test]
+    fn surviving() {
+        assert!(survives("#####.####", Mode::Walk, |r| r & 1 == 0));
+        assert!(!survives("#####.####", Mode::Walk, |_| false));
+        assert!(!survives("#####..#.#####", Mode::Walk, |r| r & 1 == 0));
+    }
+
+    #[test]
+    fn walking() {
+        let hulls = [
+            "#####.###########",
+            "#####...#########",
+            "#####..#.########",
+        ];
+
+        let (script, damage) =
+            synthesise(Mode::Walk, 10, oracle(&hulls, Mode::Walk)).unwrap();
+
+        assert_eq!(42, damage);
+        assert!(script.instructions.len() <= super::super::MAX_INSTRUCTIONS);
+        for hull in &hulls {
+            assert!(survives(hull, Mode::Walk, |r| script.jumps(r)));
+        }
+    }
+
+    #[test]
+    fn running() {
+        let hulls = [
+            "#####.###########",
+            "#####...#########",
+            "#####..#.########",
+            "#####.#.##..#.###",
+            "#####.##.##.#.###",
+            "#####.#.#...#.###",
+        ];
+
+        let (script, _) =
+            synthesise(Mode::Run, 20, oracle(&hulls, Mode::Run)).unwrap();
+
+        for hull in &hulls {
+            assert!(survives(hull, Mode::Run, |r| script.jumps(r)));
+        }
+    }
+
+    #[test]
+    fn unsolvable() {
+        let hulls = ["#....#"];
+
+        assert_eq!(
+            Err(Error::Unsolvable),
+            synthesise(Mode::Walk, 10, oracle(&hulls, Mode::Walk))
+        );
+    }
+}
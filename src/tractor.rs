@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use snafu::{ResultExt, Snafu};
+
+use crate::grid::Point;
+use crate::intcode::{self, Machine, Memory, State};
+
+/// How far right of the y axis the scanner will look for the beam, per row.
+/// The beam is assumed to stay within this slope.
+const MAX_SLOPE: i32 = 10;
+
+#[derive(Debug, PartialEq, Snafu)]
+pub enum Error {
+    #[snafu(display("Drone program failed: {}", source))]
+    Intcode { source: intcode::Error },
+    #[snafu(display("Drone reported {} for {}, {}", value, x, y))]
+    BadReading { x: i32, y: i32, value: i64 },
+    #[snafu(display("Drone stopped without reporting {}, {}", x, y))]
+    NoReading { x: i32, y: i32 },
+    #[snafu(display("Can't fit a square of size {}", size))]
+    BadSize { size: i32 },
+}
+
+/// Something that can say whether a point is pulled by the beam.
+pub trait Beam {
+    fn pulled(&mut self, point: Point) -> Result<bool, Error>;
+}
+
+/// A drone program, which has to be run from scratch for every point.
+#[derive(Debug, Clone)]
+pub struct IntcodeBeam {
+    program: Memory,
+}
+
+impl IntcodeBeam {
+    pub fn new(program: Memory) -> IntcodeBeam {
+        IntcodeBeam { program }
+    }
+}
+
+impl Beam for IntcodeBeam {
+    fn pulled(&mut self, point: Point) -> Result<bool, Error> {
+        let Point { x, y } = point;
+        let mut machine = Machine::new(self.program.clone());
+        machine.push_input(i64::from(x));
+        machine.push_input(i64::from(y));
+
+        match machine.run().context(Intcode)? {
+            State::Output(0) => Ok(false),
+            State::Output(1) => Ok(true),
+            State::Output(value) => BadReading { x, y, value }.fail(),
+            _ => NoReading { x, y }.fail(),
+        }
+    }
+}
+
+/// Where the beam crosses a row, as `start..end`.
+pub type Span = std::ops::Range<i32>;
+
+/// Maps the beam a row at a time by following its edges, rather than
+/// querying every point. Assumes the beam is a single run of points in each
+/// row and that both edges only ever move right going down.
+#[derive(Debug, Clone)]
+pub struct Scanner<B> {
+    beam: B,
+    cache: HashMap<Point, bool>,
+    rows: Vec<Option<Span>>,
+    runs: usize,
+}
+
+impl<B: Beam> Scanner<B> {
+    pub fn new(beam: B) -> Scanner<B> {
+        Scanner {
+            beam,
+            cache: HashMap::new(),
+            rows: vec![],
+            runs: 0,
+        }
+    }
+
+    /// How many times the beam has actually been queried.
+    pub fn runs(&self) -> usize {
+        self.runs
+    }
+
+    pub fn pulled(&mut self, point: Point) -> Result<bool, Error> {
+        if let Some(&pulled) = self.cache.get(&point) {
+            return Ok(pulled);
+        }
+
+        let pulled = self.beam.pulled(point)?;
+        self.runs += 1;
+        self.cache.insert(point, pulled);
+
+        Ok(pulled)
+    }
+
+    /// Where the beam crosses row `y`, or `None` if it misses the row
+    /// entirely, as it can close to the emitter.
+    pub fn row(&mut self, y: usize) -> Result<Option<Span>, Error> {
+        while self.rows.len() <= y {
+            let next = self.rows.len() as i32;
+            let span = self.track(next)?;
+            self.rows.push(span);
+        }
+
+        Ok(self.rows[y].clone())
+    }
+
+    fn track(&mut self, y: i32) -> Result<Option<Span>, Error> {
+        let previous = self.rows.iter().rev().flatten().next().cloned();
+        let (mut x, end) = match previous {
+            Some(span) => (span.start, span.end),
+            None => (0, 0),
+        };
+
+        let limit = (y + 1) * MAX_SLOPE;
+        while !self.pulled(Point { x, y })? {
+            x += 1;
+            if x > limit {
+                return Ok(None);
+            }
+        }
+
+        let start = x;
+        let mut x = end.max(start + 1);
+        // The previous row's end may have been right of this row's start
+        // without the row reaching it.
+        if !self.pulled(Point { x: x - 1, y })? {
+            x = start + 1;
+        }
+        while self.pulled(Point { x, y })? {
+            x += 1;
+        }
+
+        Ok(Some(start..x))
+    }
+
+    /// Number of points pulled in the `size` by `size` square at the
+    /// emitter.
+    pub fn affected(&mut self, size: i32) -> Result<usize, Error> {
+        let mut count = 0;
+
+        for y in 0..size.max(0) {
+            if let Some(span) = self.row(y as usize)? {
+                let end = span.end.min(size);
+                count += (end - span.start).max(0) as usize;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// The top-left corner of the nearest `size` by `size` square that fits
+    /// entirely in the beam, looking no further down than `max_y`.
+    pub fn square(
+        &mut self,
+        size: i32,
+        max_y: i32,
+    ) -> Result<Option<Point>, Error> {
+        if size < 1 {
+            return BadSize { size }.fail();
+        }
+
+        for bottom in size - 1..=max_y {
+            let x = match self.row(bottom as usize)? {
+                Some(span) => span.start,
+                None => continue,
+            };
+            let top = bottom - size + 1;
+
+            if let Some(span) = self.row(top as usize)? {
+                if span.start <= x && x + size <= span.end {
+                    return Ok(Some(Point { x, y: top }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intcode::asm::assemble;
+
+    /// A beam between the lines x = y / 2 and x = y.
+    struct Cone;
+
+    impl Beam for Cone {
+        fn pulled(&mut self, point: Point) -> Result<bool, Error> {
+            Ok(point.x <= point.y && 2 * point.x >= point.y)
+        }
+    }
+
+    /// A narrow beam that misses some rows near the emitter.
+    struct Sparse;
+
+    impl Beam for Sparse {
+        fn pulled(&mut self, point: Point) -> Result<bool, Error> {
+            let Point { x, y } = point;
+            Ok(y == 0 && x == 0 || 10 * x >= 7 * y && 10 * x <= 8 * y)
+        }
+    }
+
+    fn brute_force(beam: &mut impl Beam, size: i32) -> usize {
+        let points = (0..size).flat_map(|y| (0..size).map(move |x| (x, y)));
+
+        points
+            .filter(|&(x, y)| beam.pulled(Point { x, y }).unwrap())
+            .count()
+    }
+
+    fn brute_force_square(
+        beam: &mut impl Beam,
+        size: i32,
+        max: i32,
+    ) -> Option<Point> {
+        let mut fits = |x, y| {
+            (0..size).all(|dy| {
+                (0..size).all(|dx| {
+                    beam.pulled(Point {
+                        x: x + dx,
+                        y: y + dy,
+                    })
+                    .unwrap()
+                })
+            })
+        };
+
+        (0..max)
+            .flat_map(|y| (0..max).map(move |x| (x, y)))
+            .find(|&(x, y)| fits(x, y))
+            .map(|(x, y)| Point { x, y })
+    }
+
+    #[test]
+    fn rows() {
+        let mut scanner = Scanner::new(Cone);
+
+        assert_eq!(Some(0..1), scanner.row(0).unwrap());
+        assert_eq!(Some(5..11), scanner.row(10).unwrap());
+        assert_eq!(Some(6..12), scanner.row(11).unwrap());
+    }
+
+    #[test]
+    fn missing_rows() {
+        let mut scanner = Scanner::new(Sparse);
+
+        assert_eq!(None, scanner.row(1).unwrap());
+        assert_eq!(Some(7..9), scanner.row(10).unwrap());
+        assert_eq!(brute_force(&mut Sparse, 30), scanner.affected(30).unwrap());
+    }
+
+    #[test]
+    fn affected() {
+        let mut scanner = Scanner::new(Cone);
+
+        assert_eq!(brute_force(&mut Cone, 50), scanner.affected(50).unwrap());
+        assert!(scanner.runs() < 50 * 50 / 4);
+    }
+
+    #[test]
+    fn square() {
+        let mut scanner = Scanner::new(Cone);
+        let found = scanner.square(5, 100).unwrap();
+
+        assert_eq!(brute_force_square(&mut Cone, 5, 30), found);
+        assert_eq!(None, Scanner::new(Cone).square(5, 10).unwrap());
+        assert_eq!(
+            Err(Error::BadSize { size: 0 }),
+            Scanner::new(Cone).square(0, 10)
+        );
+    }
+
+    #[test]
+    fn queries_are_cached() {
+        let mut scanner = Scanner::new(Cone);
+        scanner.pulled(Point { x: 3, y: 4 }).unwrap();
+        scanner.pulled(Point { x: 3, y: 4 }).unwrap();
+
+        assert_eq!(1, scanner.runs());
+    }
+
+    #[test]
+    fn intcode_beam() {
+        // Pulled when y / 2 <= x <= y, like `Cone`.
+        let program = assemble(
+            "in [x]
+             in [y]
+             lt [y], [x], [t]
+             mul [x], #2, [d]
+             lt [d], [y], [u]
+             add [t], [u], [t]
+             eq [t], #0, [t]
+             out [t]
+             halt
+             x: db 0
+             y: db 0
+             t: db 0
+             d: db 0
+             u: db 0",
+        )
+        .unwrap();
+        let mut scanner = Scanner::new(IntcodeBeam::new(program));
+
+        assert_eq!(brute_force(&mut Cone, 20), scanner.affected(20).unwrap());
+    }
+}